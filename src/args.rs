@@ -0,0 +1,167 @@
+pub const USAGE: &str = "Usage: rydl [options] [file ...]
+
+Options:
+  +N              Start at line N
+  +               Start at the last line
+  +/pattern       Start at the first line containing pattern
+  +command        Run an Ex command after loading the first file
+  -c command      Run an Ex command after loading the first file
+  -R              Open files read-only
+  -es             Run Ex commands from standard input without using the terminal
+  -S file         Run Ex commands from the given file instead, implies -es
+  -u file         Use the given config file instead of ~/.rydlrc, NONE to use none
+  -               Read the buffer from standard input
+  --              Treat all following arguments as file names
+  -h, --help      Show this help
+  --version       Show the version";
+
+/// Where to put the cursor after opening the first file.
+#[derive(Debug, PartialEq)]
+pub enum Jump {
+    Line(usize),
+    LastLine,
+    Pattern(String),
+}
+
+/// The options rydl was started with.
+#[derive(Debug, Default, PartialEq)]
+pub struct Args {
+    pub files: Vec<String>,
+    pub jump: Option<Jump>,
+    pub read_only: bool,
+    /// Ex commands to run after loading the first file
+    pub commands: Vec<String>,
+    pub config: Option<String>,
+    /// Whether to read the buffer from standard input
+    pub stdin: bool,
+    /// Whether to run Ex commands without using the terminal
+    pub batch: bool,
+    /// The file to read the Ex commands from in batch mode, standard input if not given
+    pub script: Option<String>,
+}
+
+/// What rydl was asked to do on the command line.
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Edit(Args),
+    Help,
+    Version,
+}
+
+/// Parses the command-line arguments, without the program name.
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Action, String> {
+    let mut parsed = Args::default();
+    let mut args = args.into_iter();
+    let mut only_files = false;
+
+    while let Some(arg) = args.next() {
+        if only_files {
+            parsed.files.push(arg);
+            continue;
+        }
+
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Action::Help),
+            "--version" => return Ok(Action::Version),
+            "--" => only_files = true,
+            "-" => parsed.stdin = true,
+            "-R" => parsed.read_only = true,
+            "-e" | "-es" => parsed.batch = true,
+            "-S" => {
+                let script = args.next().ok_or("Argument missing after: -S")?;
+                parsed.script = Some(script);
+                parsed.batch = true;
+            }
+            "-c" => {
+                let command = args.next().ok_or("Argument missing after: -c")?;
+                parsed.commands.push(command);
+            }
+            "-u" => {
+                let config = args.next().ok_or("Argument missing after: -u")?;
+                parsed.config = Some(config);
+            }
+            "+" => parsed.jump = Some(Jump::LastLine),
+            _ if arg.starts_with('+') => {
+                let rest = &arg[1..];
+
+                if let Some(pattern) = rest.strip_prefix('/') {
+                    parsed.jump = Some(Jump::Pattern(String::from(pattern)));
+                } else if rest.chars().all(|c| c.is_ascii_digit()) {
+                    let line = rest
+                        .parse()
+                        .map_err(|_| format!("Invalid line number: {}", rest))?;
+                    parsed.jump = Some(Jump::Line(line));
+                } else {
+                    parsed.commands.push(String::from(rest));
+                }
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ => parsed.files.push(arg),
+        }
+    }
+
+    if parsed.batch && parsed.stdin && parsed.script.is_none() {
+        return Err(String::from(
+            "Can't read both the buffer and the commands from standard input",
+        ));
+    }
+
+    if parsed.stdin && !parsed.files.is_empty() {
+        return Err(String::from(
+            "Reading from standard input can't be combined with files",
+        ));
+    }
+
+    Ok(Action::Edit(parsed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(args: &str) -> Result<Action, String> {
+        parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn parse_test() {
+        let args = Args {
+            files: vec![String::from("a.txt"), String::from("-b.txt")],
+            jump: Some(Jump::Line(12)),
+            read_only: true,
+            commands: vec![String::from("set"), String::from("q")],
+            config: Some(String::from("NONE")),
+            stdin: false,
+            batch: false,
+            script: None,
+        };
+
+        assert_eq!(
+            Ok(Action::Edit(args)),
+            parse_str("-R +12 a.txt -c set -u NONE +q -- -b.txt")
+        );
+
+        assert_eq!(Ok(Action::Help), parse_str("a.txt --help"));
+        assert_eq!(Ok(Action::Version), parse_str("--version"));
+        assert!(parse_str("-x").is_err());
+        assert!(parse_str("-c").is_err());
+        assert!(parse_str("- a.txt").is_err());
+        assert!(parse_str("-es -").is_err());
+
+        match parse_str("-S script.ex -") {
+            Ok(Action::Edit(args)) => {
+                assert!(args.batch && args.stdin);
+                assert_eq!(Some(String::from("script.ex")), args.script);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        match parse_str("+/fn main") {
+            Ok(Action::Edit(args)) => {
+                assert_eq!(Some(Jump::Pattern(String::from("fn"))), args.jump);
+                assert_eq!(vec![String::from("main")], args.files);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -1,10 +1,33 @@
+use crate::error::{Error, Result};
+use crate::io::DiskState;
+use crate::settings::Options;
+
 pub struct Buffer {
     lines: Vec<String>,
+
+    /// Buffer-local option values, e.g. `tabstop`
+    pub options: Options,
+
+    /// Whether the file does not exist on disk yet
+    pub is_new: bool,
+    /// The state of the file on disk when it was last read or written
+    pub disk_state: Option<DiskState>,
+    /// Whether the user was already told that the file changed on disk
+    pub disk_change_shown: bool,
+    /// Whether invalid bytes of the file are kept as escape characters, see `encoding::decode`
+    pub escaped: bool,
 }
 
 impl Buffer {
     pub fn new(init: bool) -> Self {
-        let mut buffer = Self { lines: Vec::new() };
+        let mut buffer = Self {
+            lines: Vec::new(),
+            options: Options::new(),
+            is_new: false,
+            disk_state: None,
+            disk_change_shown: false,
+            escaped: false,
+        };
 
         if init {
             buffer.push(String::new());
@@ -21,12 +44,27 @@ impl Buffer {
         self.lines.len()
     }
 
-    pub fn iter(&self) -> std::slice::Iter<String> {
+    pub fn iter(&self) -> std::slice::Iter<'_, String> {
         self.lines.iter()
     }
 
-    pub fn set() {
-        // TODO
+    /// Checks that a line number is in the buffer.
+    fn check(&self, line_number: usize) -> Result<()> {
+        if (1..=self.lines.len()).contains(&line_number) {
+            Ok(())
+        } else {
+            Err(Error::LineOutOfRange {
+                line: line_number,
+                len: self.lines.len(),
+            })
+        }
+    }
+
+    /// Replaces the content of a line, returning the previous content.
+    pub fn set(&mut self, line_number: usize, line: String) -> Result<String> {
+        self.check(line_number)?;
+
+        Ok(std::mem::replace(&mut self.lines[line_number - 1], line))
     }
 
     pub fn get(&self, line_number: usize) -> Option<&String> {
@@ -45,6 +83,20 @@ impl Buffer {
         }
     }
 
+    /// Like `get`, but with an error for line numbers that are not in the buffer.
+    pub fn line(&self, line_number: usize) -> Result<&String> {
+        self.check(line_number)?;
+
+        Ok(&self.lines[line_number - 1])
+    }
+
+    /// Like `get_mut`, but with an error for line numbers that are not in the buffer.
+    pub fn line_mut(&mut self, line_number: usize) -> Result<&mut String> {
+        self.check(line_number)?;
+
+        Ok(&mut self.lines[line_number - 1])
+    }
+
     pub fn last(&self) -> Option<&String> {
         self.lines.last()
     }
@@ -61,24 +113,40 @@ impl Buffer {
         self.lines.pop()
     }
 
-    pub fn insert(&mut self, line_number: usize, line: String) {
+    /// Inserts a line before the given line number, which may be one after the last line.
+    pub fn insert(&mut self, line_number: usize, line: String) -> Result<()> {
+        if line_number != self.lines.len() + 1 {
+            self.check(line_number)?;
+        }
+
         self.lines.insert(line_number - 1, line);
+
+        Ok(())
     }
 
-    pub fn remove(&mut self, line_number: usize) -> String {
-        self.lines.remove(line_number - 1)
+    pub fn remove(&mut self, line_number: usize) -> Result<String> {
+        self.check(line_number)?;
+
+        Ok(self.lines.remove(line_number - 1))
     }
 
-    // TODO use error type
-    pub fn replace_line(&mut self, line_number: usize, line: String) -> Result<(), ()> {
-        if (1..=self.lines.len()).contains(&line_number) {
-            self.lines.remove(line_number - 1);
-            self.lines.insert(line_number - 1, line);
+    pub fn replace_line(&mut self, line_number: usize, line: String) -> Result<()> {
+        self.set(line_number, line).map(|_| ())
+    }
 
-            Ok(())
-        } else {
-            Err(())
-        }
+    /// Appends the next line to a line, with a separator in between. Returns the length of the
+    /// line before, in bytes.
+    pub fn join(&mut self, line_number: usize, separator: &str) -> Result<usize> {
+        self.check(line_number + 1)?;
+
+        let next = self.lines.remove(line_number);
+        let line = &mut self.lines[line_number - 1];
+        let len = line.len();
+
+        line.push_str(separator);
+        line.push_str(&next);
+
+        Ok(len)
     }
 }
 
@@ -120,4 +188,25 @@ mod tests {
         assert_eq!("New line 2", buffer.get(2).unwrap());
         assert_eq!("New line 5", buffer.get(5).unwrap());
     }
+
+    #[test]
+    fn range_test() {
+        let mut buffer = Buffer::new(true);
+
+        assert_eq!("", buffer.set(1, String::from("first")).unwrap());
+        assert!(buffer.set(2, String::from("second")).is_err());
+
+        buffer.insert(2, String::from("third")).unwrap();
+        buffer.insert(2, String::from("second")).unwrap();
+        assert!(buffer.insert(5, String::new()).is_err());
+        assert!(buffer.insert(0, String::new()).is_err());
+
+        assert_eq!("second", buffer.remove(2).unwrap());
+        assert!(buffer.remove(3).is_err());
+        assert_eq!(vec!["first", "third"], buffer.iter().collect::<Vec<_>>());
+
+        assert_eq!(5, buffer.join(1, " ").unwrap());
+        assert_eq!(vec!["first third"], buffer.iter().collect::<Vec<_>>());
+        assert!(buffer.join(1, "").is_err());
+    }
 }
@@ -0,0 +1,376 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::handler::COMMANDS;
+use crate::settings::OPTIONS;
+use crate::util::{self, byte_index, grapheme_count};
+
+/// The file the command-line history is kept in between sessions.
+pub const HISTORY_FILE: &str = "~/.rydl_history";
+
+/// Commands that take a file name, and commands that take a buffer name as argument.
+const FILE_COMMANDS: &[&str] = &[
+    "e", "edit", "vie", "view", "w", "write", "sav", "saveas", "wq", "x", "xit", "n", "next",
+];
+const BUFFER_COMMANDS: &[&str] = &["b", "buffer"];
+
+/// The text typed into the command line, together with the cursor in it.
+#[derive(Default)]
+pub struct CommandLine {
+    pub text: String,
+    /// The (0-based) grapheme index of the cursor
+    pub cursor: usize,
+}
+
+impl CommandLine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the text, with the cursor behind it.
+    pub fn set(&mut self, text: &str) {
+        self.text = String::from(text);
+        self.cursor = grapheme_count(text);
+    }
+
+    /// Gets the text before the cursor.
+    pub fn before_cursor(&self) -> &str {
+        &self.text[..byte_index(&self.text, self.cursor)]
+    }
+
+    pub fn insert(&mut self, text: &str) {
+        let index = byte_index(&self.text, self.cursor);
+
+        self.text.insert_str(index, text);
+        self.cursor += grapheme_count(text);
+    }
+
+    /// Replaces the text between a byte index and the cursor, e.g. with a completion.
+    pub fn replace_before_cursor(&mut self, start: usize, text: &str) {
+        let end = byte_index(&self.text, self.cursor);
+
+        self.text.replace_range(start..end, text);
+        self.cursor = grapheme_count(&self.text[..start + text.len()]);
+    }
+
+    /// Deletes the grapheme before the cursor.
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.delete_range(self.cursor - 1, self.cursor);
+        }
+    }
+
+    /// Deletes the grapheme under the cursor.
+    pub fn delete(&mut self) {
+        if self.cursor < grapheme_count(&self.text) {
+            self.delete_range(self.cursor, self.cursor + 1);
+        }
+    }
+
+    /// Deletes the word before the cursor together with the whitespace behind it (`Ctrl-w`).
+    pub fn delete_word(&mut self) {
+        self.delete_range(util::word_start(&self.text, self.cursor), self.cursor);
+    }
+
+    /// Deletes everything before the cursor (`Ctrl-u`).
+    pub fn delete_to_start(&mut self) {
+        self.delete_range(0, self.cursor);
+    }
+
+    pub fn left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn right(&mut self) {
+        if self.cursor < grapheme_count(&self.text) {
+            self.cursor += 1;
+        }
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = grapheme_count(&self.text);
+    }
+
+    fn delete_range(&mut self, from: usize, to: usize) {
+        let range = byte_index(&self.text, from)..byte_index(&self.text, to);
+
+        self.text.replace_range(range, "");
+        self.cursor = from;
+    }
+}
+
+/// The command lines entered before, the oldest first.
+pub struct CommandHistory {
+    entries: Vec<String>,
+    /// The number of entries that are kept
+    pub size: usize,
+}
+
+impl CommandHistory {
+    pub fn new(size: usize) -> Self {
+        Self {
+            entries: Vec::new(),
+            size,
+        }
+    }
+
+    /// Reads the history from a file, one entry per line.
+    pub fn load(&mut self, file_name: &str) -> io::Result<()> {
+        let text = fs::read_to_string(util::expand_home(file_name))?;
+
+        for line in text.lines() {
+            self.add(line);
+        }
+
+        Ok(())
+    }
+
+    /// Writes the history to a file that only the user can read. It is replaced as a whole, so
+    /// that it is never left half written.
+    pub fn save(&self, file_name: &str) -> io::Result<()> {
+        let mut text = self.entries.join("\n");
+        text.push('\n');
+
+        let path = util::expand_home(file_name);
+        let temp = PathBuf::from(format!("{}.{}.tmp", path.display(), process::id()));
+
+        // A file left over by a crash of a process with the same id is not in use anymore
+        let _ = fs::remove_file(&temp);
+
+        let result = util::create_private(&temp, true)
+            .and_then(|mut file| file.write_all(text.as_bytes()))
+            .and_then(|()| fs::rename(&temp, &path));
+
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+
+        result
+    }
+
+    /// Adds an entry as the newest one, an older copy of it is dropped.
+    pub fn add(&mut self, entry: &str) {
+        if entry.trim().is_empty() {
+            return;
+        }
+
+        self.entries.retain(|e| e != entry);
+        self.entries.push(String::from(entry));
+
+        if self.entries.len() > self.size {
+            let excess = self.entries.len() - self.size;
+            self.entries.drain(..excess);
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Finds the newest entry before the given index that starts with the prefix.
+    pub fn previous(&self, prefix: &str, before: usize) -> Option<usize> {
+        (0..before.min(self.entries.len()))
+            .rev()
+            .find(|&i| self.entries[i].starts_with(prefix))
+    }
+
+    /// Finds the oldest entry after the given index that starts with the prefix.
+    pub fn next(&self, prefix: &str, after: usize) -> Option<usize> {
+        (after + 1..self.entries.len()).find(|&i| self.entries[i].starts_with(prefix))
+    }
+}
+
+/// Finds the completions for the last word of a command line: command names, option names for
+/// `:set`, buffer names for `:buffer` and file paths for commands that take a file. Returns the
+/// byte index the word starts at together with the candidates.
+pub fn complete(text: &str, buffers: &[String]) -> (usize, Vec<String>) {
+    let trimmed = text.trim_start_matches(':');
+    let offset = text.len() - trimmed.len();
+    let text = trimmed;
+    let start = text.rfind(' ').map_or(0, |i| i + 1);
+    let word = &text[start..];
+
+    let candidates = match text[..start].split_whitespace().next() {
+        None => COMMANDS
+            .iter()
+            .filter(|command| command.starts_with(word))
+            .map(|command| String::from(*command))
+            .collect(),
+        Some(command) => match command.trim_end_matches('!') {
+            "se" | "set" | "setl" | "setlocal" if !word.contains('=') => {
+                let (prefix, name) = ["no", "inv"]
+                    .iter()
+                    .find_map(|prefix| {
+                        word.strip_prefix(prefix)
+                            .filter(|_| !OPTIONS.iter().any(|def| def.name.starts_with(word)))
+                            .map(|name| (*prefix, name))
+                    })
+                    .unwrap_or(("", word));
+
+                OPTIONS
+                    .iter()
+                    .filter(|def| def.name.starts_with(name))
+                    .map(|def| format!("{}{}", prefix, def.name))
+                    .collect()
+            }
+            command if BUFFER_COMMANDS.contains(&command) => buffers
+                .iter()
+                .filter(|name| name.starts_with(word))
+                .cloned()
+                .collect(),
+            command if FILE_COMMANDS.contains(&command) => complete_path(word),
+            _ => Vec::new(),
+        },
+    };
+
+    (offset + start, candidates)
+}
+
+/// Lists the files and directories that start with the given path, directories end with `/`.
+fn complete_path(path: &str) -> Vec<String> {
+    let (dir, prefix) = match path.rfind('/') {
+        Some(i) => (&path[..=i], &path[i + 1..]),
+        None => ("", path),
+    };
+    let read_from = if dir.is_empty() {
+        Path::new(".").to_path_buf()
+    } else {
+        util::expand_home(dir)
+    };
+
+    let entries = match fs::read_dir(read_from) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut candidates: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+
+            // Hidden files only if they are asked for
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+
+            Some(format!("{}{}{}", dir, name, if is_dir { "/" } else { "" }))
+        })
+        .collect();
+
+    candidates.sort();
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn editing_test() {
+        let mut line = CommandLine::new();
+
+        line.insert("set ts=8");
+        line.left();
+        line.backspace();
+        assert_eq!("set ts8", line.text);
+
+        line.home();
+        line.delete();
+        line.end();
+        line.insert(" et");
+        assert_eq!("et ts8 et", line.text);
+
+        line.delete_word();
+        assert_eq!("et ts8 ", line.text);
+        line.delete_word();
+        assert_eq!("et ", line.text);
+
+        line.set("e foo/bar");
+        line.delete_word();
+        assert_eq!("e foo/", line.text);
+
+        line.left();
+        line.delete_to_start();
+        assert_eq!(("/", 0), (line.text.as_str(), line.cursor));
+    }
+
+    #[test]
+    fn history_test() {
+        let mut history = CommandHistory::new(3);
+
+        for entry in &["set ts=8", "w", "set et", "w", "q"] {
+            history.add(entry);
+        }
+
+        // The oldest entry is dropped, the repeated `w` moves to the end
+        assert_eq!(3, history.len());
+        assert_eq!(Some("set et"), history.get(0));
+
+        assert_eq!(Some(0), history.previous("set", history.len()));
+        assert_eq!(None, history.previous("set", 0));
+        assert_eq!(Some(2), history.next("", 1));
+        assert_eq!(None, history.next("set", 0));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn history_file_test() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = util::TempDir::new("history");
+        let path = dir.join("history");
+        let file_name = path.to_str().unwrap();
+
+        let mut history = CommandHistory::new(3);
+        history.add("e secret.txt");
+        history.save(file_name).unwrap();
+        history.add("w");
+        history.save(file_name).unwrap();
+
+        // The history is private and only the file itself is left
+        assert_eq!(
+            0o600,
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777
+        );
+        assert_eq!(1, fs::read_dir(dir.path()).unwrap().count());
+
+        let mut loaded = CommandHistory::new(3);
+        loaded.load(file_name).unwrap();
+        assert_eq!(Some("w"), loaded.get(1));
+    }
+
+    #[test]
+    fn complete_test() {
+        let (start, candidates) = complete("wr", &[]);
+        assert_eq!((0, vec![String::from("write")]), (start, candidates));
+
+        let (start, candidates) = complete("set noexpa", &[]);
+        assert_eq!((4, vec![String::from("noexpandtab")]), (start, candidates));
+
+        let (_, candidates) = complete("set ts=", &[]);
+        assert!(candidates.is_empty());
+
+        let buffers = vec![String::from("main.rs"), String::from("lib.rs")];
+        let (start, candidates) = complete("b m", &buffers);
+        assert_eq!((2, vec![String::from("main.rs")]), (start, candidates));
+
+        let (_, candidates) = complete("e src/mai", &[]);
+        assert_eq!(vec![String::from("src/main.rs")], candidates);
+    }
+}
@@ -1,31 +1,39 @@
 use std::cmp;
 use std::io::{stdout, Write};
-use termion::color;
 use termion::raw::IntoRawMode;
+use termion::{color, style, terminal_size};
 
+use crate::cmdline::CommandLine;
 use crate::editor::{Editor, Mode as EditorMode};
+use crate::message::Severity;
+use crate::statusline;
+use crate::terminal;
+use crate::util::{expand_tabs, grapheme_count};
 
 pub trait Drawer {
     fn draw(&mut self);
     fn draw_bar(&mut self);
-    fn draw_bar_text(&mut self, text: String, bar_color: termion::color::Rgb);
-    fn draw_bar_empty(&mut self);
+    fn draw_message(&mut self);
+    fn draw_command_line(&mut self, line: &CommandLine);
+    fn draw_wildmenu(&mut self, candidates: &[String], selected: usize);
     fn draw_line_numbers(&mut self);
     fn draw_buffer(&mut self);
     fn draw_cursor(&mut self);
+    fn redraw(&mut self);
 }
 
 impl Drawer for Editor {
     fn draw(&mut self) {
+        if self.headless {
+            return;
+        }
+
         let mut stdout = stdout().into_raw_mode().unwrap();
 
         write!(stdout, "{}", termion::cursor::Hide).unwrap();
 
-        if self.keep_bar > 0 {
-            self.keep_bar -= 1;
-        } else {
-            self.draw_bar();
-        }
+        self.draw_bar();
+        self.draw_message();
 
         self.draw_buffer();
 
@@ -56,27 +64,16 @@ impl Drawer for Editor {
         )
         .unwrap();
 
-        // Draw mode
-        if self.mode != EditorMode::Command {
-            write!(
-                stdout,
-                "{}{}-- {} --",
-                color::Fg(color::Black),
-                termion::cursor::Goto(2, self.height - 1),
-                self.mode.name()
-            )
-            .unwrap();
-        }
+        // Draw status line
+        let format = self.option("statusline");
+        let (left, right) = statusline::render(self, format.as_str().unwrap());
 
-        // Draw column and row
         write!(
             stdout,
-            "{}{}{},{},{}",
+            "{}{}{}",
             color::Fg(color::Black),
-            termion::cursor::Goto(self.width - 10, self.height - 1),
-            self.current_line,
-            self.current_char,
-            self.x
+            termion::cursor::Goto(2, self.height - 1),
+            statusline::fit(&left, &right, self.width.saturating_sub(2) as usize)
         )
         .unwrap();
 
@@ -90,71 +87,129 @@ impl Drawer for Editor {
         .unwrap();
     }
 
-    fn draw_bar_text(&mut self, text: String, bar_color: termion::color::Rgb) {
+    /// Draws the message line below the status line, in a color depending on the severity.
+    fn draw_message(&mut self) {
         let mut stdout = stdout().into_raw_mode().unwrap();
 
-        let output = " ".repeat(self.width as usize);
-
-        // Draw bar
         write!(
             stdout,
-            "{}{}{}",
-            color::Bg(bar_color),
-            termion::cursor::Goto(1, self.height - 1),
-            output
+            "{}{}",
+            termion::cursor::Goto(1, self.height),
+            termion::clear::CurrentLine
         )
         .unwrap();
 
-        // Draw text
-        write!(
-            stdout,
-            "{}{}{}",
-            color::Fg(color::Black),
-            termion::cursor::Goto(2, self.height - 1),
-            text
-        )
-        .unwrap();
+        if let Some(message) = &self.message {
+            let fg = match message.severity {
+                Severity::Info => color::Fg(color::Reset).to_string(),
+                Severity::Warning => color::Fg(color::Rgb(0xcb, 0xb5, 0x25)).to_string(),
+                Severity::Error => color::Fg(color::Rgb(0xf4, 0x59, 0x05)).to_string(),
+            };
+            // Writing the last column could scroll the screen
+            let text: String = message
+                .text
+                .lines()
+                .next()
+                .unwrap_or("")
+                .chars()
+                .take(self.width.saturating_sub(1) as usize)
+                .collect();
+
+            write!(stdout, "{}{}{}", fg, text, color::Fg(color::Reset)).unwrap();
+        }
+
+        write!(stdout, "{}", termion::cursor::Goto(self.x, self.y)).unwrap();
+        stdout.flush().unwrap();
+    }
+
+    /// Draws the command line in place of the message line, with the cursor in it.
+    fn draw_command_line(&mut self, line: &CommandLine) {
+        let mut stdout = stdout().into_raw_mode().unwrap();
 
         write!(
             stdout,
-            "{}{}{}",
-            color::Fg(color::Reset),
-            color::Bg(color::Reset),
-            termion::cursor::Goto(self.x, self.y)
+            "{}{}:{}{}",
+            termion::cursor::Goto(1, self.height),
+            termion::clear::CurrentLine,
+            line.text,
+            termion::cursor::Goto(2 + grapheme_count(line.before_cursor()) as u16, self.height)
         )
         .unwrap();
-
-        self.keep_bar = 1;
+        stdout.flush().unwrap();
     }
 
-    fn draw_bar_empty(&mut self) {
+    /// Shows completion candidates in place of the status line, the selected one highlighted.
+    /// If they don't fit, only those around the selected one are shown.
+    fn draw_wildmenu(&mut self, candidates: &[String], selected: usize) {
         let mut stdout = stdout().into_raw_mode().unwrap();
+        let width = self.width as usize;
 
-        let output = " ".repeat(self.width as usize);
+        // Fill the line page by page until the selected candidate is on it
+        let mut first = 0;
+        let mut used = 0;
+
+        for (i, candidate) in candidates.iter().enumerate() {
+            let len = grapheme_count(candidate) + 2;
+
+            if used + len > width && i > first {
+                if i > selected {
+                    break;
+                }
+
+                first = i;
+                used = 0;
+            }
+
+            used += len;
+        }
 
-        // Draw bar
         write!(
             stdout,
-            "{}{}{}",
-            color::Bg(color::Rgb(0xcb, 0xb5, 0x25)),
+            "{}{}{}{}",
             termion::cursor::Goto(1, self.height - 1),
-            output
+            color::Bg(color::Rgb(0xcb, 0xb5, 0x25)),
+            color::Fg(color::Black),
+            " ".repeat(width)
         )
         .unwrap();
+        write!(stdout, "{}", termion::cursor::Goto(1, self.height - 1)).unwrap();
+
+        let mut used = 0;
+
+        for (i, candidate) in candidates.iter().enumerate().skip(first) {
+            let len = grapheme_count(candidate) + 2;
+
+            if used + len > width && i > first {
+                break;
+            }
+
+            if i == selected {
+                write!(
+                    stdout,
+                    " {}{}{} ",
+                    style::Invert,
+                    candidate,
+                    style::NoInvert
+                )
+                .unwrap();
+            } else {
+                write!(stdout, " {} ", candidate).unwrap();
+            }
+
+            used += len;
+        }
 
         write!(
             stdout,
-            "{}{}{}",
+            "{}{}",
             color::Fg(color::Reset),
-            color::Bg(color::Reset),
-            termion::cursor::Goto(self.x, self.y)
+            color::Bg(color::Reset)
         )
         .unwrap();
     }
 
     fn draw_line_numbers(&mut self) {
         let mut stdout = stdout().into_raw_mode().unwrap();
-        let mut y = 1;
 
         let from = self.top_line();
         let to = self.top_line()
@@ -163,7 +218,7 @@ impl Drawer for Editor {
                 usize::from(self.height) - 4,
             );
 
-        for number in from..=to {
+        for (y, number) in (1..).zip(from..=to) {
             let mut number = number.to_string();
             while number.len() < self.start_x() as usize - 2 {
                 number.insert(0, ' ');
@@ -177,14 +232,11 @@ impl Drawer for Editor {
                 number
             )
             .unwrap();
-
-            y += 1;
         }
     }
 
     fn draw_buffer(&mut self) {
         let mut stdout = stdout().into_raw_mode().unwrap();
-        let mut y = 1;
 
         //self.ys_without_own_line = Vec::new();
 
@@ -195,26 +247,16 @@ impl Drawer for Editor {
                 usize::from(self.height) - 4,
             );
 
-        for i in from..=to {
+        let tab_width = self.tab_width();
+
+        for (y, i) in (1..).zip(from..=to) {
             let line = self.buffer.get(i).unwrap();
 
-            // Replace tabs with spaces for printing
-            let mut new_line = String::new();
-            for (i, c) in line.chars().enumerate() {
-                match c {
-                    '\t' => {
-                        new_line.push_str(
-                            std::iter::repeat(" ")
-                                .take(self.settings.tab_width - (i % self.settings.tab_width))
-                                .collect::<String>()
-                                .as_str(),
-                        );
-                    }
-                    _ => {
-                        new_line.push(c);
-                    }
-                }
-            }
+            // Replace tabs with spaces and control characters with visible symbols for printing
+            let new_line: String = expand_tabs(line, tab_width)
+                .chars()
+                .map(control_picture)
+                .collect();
 
             write!(
                 stdout,
@@ -225,8 +267,6 @@ impl Drawer for Editor {
             )
             .unwrap();
 
-            y += 1;
-
             /*match self.buffer.get(i) {
                 Some(line) => {
                     let mut first_part = true;
@@ -257,11 +297,55 @@ impl Drawer for Editor {
 
             y += 1;*/
         }
+
+        // Clear what is left of lines that were removed
+        for y in (to - from + 2)..=usize::from(self.height).saturating_sub(3) {
+            write!(
+                stdout,
+                "{}{}",
+                termion::cursor::Goto(self.start_x(), y as u16),
+                termion::clear::UntilNewline
+            )
+            .unwrap();
+        }
     }
 
     fn draw_cursor(&mut self) {
         let mut stdout = stdout().into_raw_mode().unwrap();
 
+        // A bar shows that text is inserted between characters, an underline that it replaces them
+        let shape = match self.mode {
+            EditorMode::Command => terminal::CURSOR_BLOCK,
+            EditorMode::Insert => terminal::CURSOR_BAR,
+            EditorMode::Replace => terminal::CURSOR_UNDERLINE,
+        };
+
+        write!(stdout, "{}", shape).unwrap();
+
         write!(stdout, "{}", termion::cursor::Goto(self.x, self.y)).unwrap();
     }
+
+    /// Draws everything from scratch, e.g. after the terminal was used by another program.
+    fn redraw(&mut self) {
+        if let Ok((width, height)) = terminal_size() {
+            self.width = width;
+            self.height = height;
+        }
+
+        let mut stdout = stdout().into_raw_mode().unwrap();
+        write!(stdout, "{}", termion::clear::All).unwrap();
+
+        self.set_top_line(self.top_line());
+        self.draw();
+    }
+}
+
+/// Gets the symbol a control character is shown as, like `␛` for escape. Other characters stay
+/// as they are.
+fn control_picture(c: char) -> char {
+    match c as u32 {
+        code @ 0..=0x1f => char::from_u32(0x2400 + code).unwrap(),
+        0x7f => '\u{2421}',
+        _ => c,
+    }
 }
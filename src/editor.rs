@@ -1,18 +1,49 @@
-use std::io::{stdin, stdout, Write};
-use termion::event::Key;
-use termion::input::TermRead;
-use termion::raw::IntoRawMode;
-use termion::{color, terminal_size};
+use std::cmp;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::ErrorKind;
+use std::mem;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::Instant;
 
+use crate::args::{Args, Jump};
 use crate::buffer::Buffer;
-use crate::drawer::Drawer;
+use crate::cmdline::CommandHistory;
+use crate::error::{Error, Result};
 use crate::handler::Handler;
-use crate::settings::Settings;
+use crate::indent::Indent;
+use crate::io::IO;
+use crate::key::Key;
+use crate::keymap::{self, Keymap, MapCommand, MapMode, Mapping};
+use crate::message::{History, Message, Severity};
+use crate::register::{Register, Registers};
+use crate::settings::{find, SetArg, Settings, TabType, Value, FILE_OPTIONS};
+use crate::swap::{Swap, SwapChoice};
+use crate::util;
 
-#[derive(PartialEq)]
+#[cfg(feature = "terminal")]
+use {
+    crate::cmdline::{self, CommandLine, HISTORY_FILE},
+    crate::drawer::Drawer,
+    crate::input::{Event, Input},
+    crate::keymap::Resolved,
+    crate::terminal,
+    std::io::{stdout, Write},
+    std::time::Duration,
+    termion::raw::IntoRawMode,
+    termion::{color, terminal_size},
+};
+
+/// The config file that is read on start if no other one is given.
+pub const CONFIG_FILE: &str = "~/.rydlrc";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Mode {
     Command,
     Insert,
+    /// Like Insert mode, but typed characters overwrite the ones under the cursor
+    Replace,
 }
 
 impl Mode {
@@ -20,6 +51,7 @@ impl Mode {
         match *self {
             Self::Command => String::from("COMMAND"),
             Self::Insert => String::from("INSERT"),
+            Self::Replace => String::from("REPLACE"),
         }
     }
 }
@@ -39,22 +71,76 @@ pub struct Editor {
     pub x: u16,
     pub y: u16,
     pub mode: Mode,
+    /// The operator waiting for a motion, like `d` in `dd`, or `r` waiting for a character
+    pub pending_operator: Option<char>,
+    /// The count typed before a command, 0 if there is none
+    pub count: usize,
+    /// An Insert-mode key waiting for the next key, `r` for `Ctrl-r` and `v` for `Ctrl-v`
+    pub pending_insert: Option<char>,
+    /// The mode to go back to after one command, see `Ctrl-o`
+    pub mode_after_command: Option<Mode>,
+    /// The keys of the change being typed, including its count and the text typed in Insert
+    /// mode. `insert_start` is the index of the first key typed in Insert mode.
+    pub change: Vec<Key>,
+    pub insert_start: Option<usize>,
+    /// The keys of the last change, repeated with `.`
+    pub last_change: Vec<Key>,
+    /// The graphemes overwritten in Replace mode, `None` for characters that were appended.
+    /// `Backspace` puts them back.
+    pub replaced: Vec<Option<String>>,
 
     pub file_name: String,
+    /// The files given on the command line, `arg_index` is the one being edited
+    pub arglist: Vec<String>,
+    pub arg_index: usize,
+    pub swap_file: Option<PathBuf>,
+    pub changes_since_swap: usize,
+    pub last_swap: Instant,
 
     pub settings: Settings,
+    pub registers: Registers,
+    pub keymap: Keymap,
+    /// Keys that were typed or come from mappings but are not handled yet, with whether they
+    /// may be mapped
+    pub typeahead: VecDeque<(Key, bool)>,
+    pub command_history: CommandHistory,
+    #[cfg(feature = "terminal")]
+    pub input: Input,
 
     pub running: bool,
+    pub headless: bool,
+    /// Every file that is opened is read-only (`-R`)
+    pub read_only_mode: bool,
+    /// The number of errors shown so far
+    pub error_count: usize,
     pub modified: bool,
     pub top_line_changed: bool,
-    pub keep_bar: usize,
+    /// The message shown in the message line
+    pub message: Option<Message>,
+    pub messages: History,
 }
 
 impl Editor {
     /// Creates a new rydl instance.
+    // No `Default` as this needs a terminal
+    #[allow(clippy::new_without_default)]
+    #[cfg(feature = "terminal")]
     pub fn new() -> Self {
         let (width, height) = terminal_size().expect("Could not get terminal size.");
 
+        Self::with_size(width, height)
+    }
+
+    /// Creates a rydl instance that doesn't use the terminal at all, e.g. to run scripts. Errors
+    /// go to stderr, other messages are dropped.
+    pub fn headless() -> Self {
+        let mut editor = Self::with_size(80, 24);
+        editor.headless = true;
+
+        editor
+    }
+
+    fn with_size(width: u16, height: u16) -> Self {
         Self {
             width,
             height,
@@ -69,31 +155,47 @@ impl Editor {
             x: 1,
             y: 1,
             mode: Mode::Command,
+            pending_operator: None,
+            count: 0,
+            pending_insert: None,
+            mode_after_command: None,
+            change: Vec::new(),
+            insert_start: None,
+            last_change: Vec::new(),
+            replaced: Vec::new(),
 
             file_name: String::new(),
+            arglist: Vec::new(),
+            arg_index: 0,
+            swap_file: None,
+            changes_since_swap: 0,
+            last_swap: Instant::now(),
 
             settings: Settings::new(),
+            registers: Registers::new(),
+            keymap: Keymap::new(),
+            typeahead: VecDeque::new(),
+            command_history: CommandHistory::new(200),
+            #[cfg(feature = "terminal")]
+            input: Input::new(),
 
             running: true,
+            headless: false,
+            read_only_mode: false,
+            error_count: 0,
             modified: false,
             top_line_changed: true,
-            keep_bar: 0,
+            message: None,
+            messages: History::new(),
         }
     }
 
-    /// Initializes a rydl instance, i.e. it clears the screen, resets the cursor and calls the drawer once.
+    /// Initializes a rydl instance, i.e. it switches to the alternate screen, resets the cursor and calls the drawer once.
+    #[cfg(feature = "terminal")]
     pub fn init(&mut self) {
         self.x = self.start_x();
 
-        #[allow(clippy::explicit_write)]
-        write!(
-            stdout(),
-            "{}{}{}",
-            termion::clear::All,
-            termion::cursor::Goto(1, 1),
-            termion::cursor::Show
-        )
-        .unwrap();
+        terminal::enter();
         self.draw();
     }
 
@@ -107,34 +209,18 @@ impl Editor {
     }
 
     /// Used to move the cursor to the left if possible (both the on-screen and the internal buffer cursor).
-    pub fn move_cursor_left(&mut self, c: char) {
+    pub fn move_cursor_left(&mut self) {
         if self.current_char > 1 {
-            // If the character to move over is a tab, we need to set back the cursor by multiple single width chars
-            if c == '\t' {
-                let sub = (self.settings.tab_width - (self.current_char % self.settings.tab_width)
-                    + 1) as u16;
-                self.x -= if sub != 5 { sub } else { 1 };
-            } else {
-                self.x -= 1;
-            }
-
             self.current_char -= 1;
+            self.sync_x();
         }
     }
 
     /// Used to move the cursor to the right if possible (both the on-screen and the internal buffer cursor).
-    pub fn move_cursor_right(&mut self, c: char) {
-        if self.current_char <= self.buffer.get(self.current_line).unwrap().len() {
-            // If the character to move over is a tab, we need to advance the cursor by multiple single width chars
-            if c == '\t' {
-                let add = (self.settings.tab_width - (self.current_char % self.settings.tab_width)
-                    + 1) as u16;
-                self.x += if add != 5 { add } else { 1 };
-            } else {
-                self.x += 1;
-            }
-
+    pub fn move_cursor_right(&mut self) {
+        if self.current_char <= self.current_line_len() {
             self.current_char += 1;
+            self.sync_x();
         }
     }
 
@@ -149,11 +235,8 @@ impl Editor {
                 self.set_top_line(self.top_line() - 1);
             }
 
-            if self.current_char > self.buffer.get(self.current_line).unwrap().len() {
-                self.current_char = self.buffer.get(self.current_line).unwrap().len() + 1;
-                // TODO make + 4 variable depending on the length of the line numbers
-                self.x = (self.buffer.get(self.current_line).unwrap().len() + 1 + 4) as u16;
-            }
+            self.current_char = cmp::min(self.current_char, self.current_line_len() + 1);
+            self.sync_x();
         }
     }
 
@@ -168,91 +251,1017 @@ impl Editor {
                 self.set_top_line(self.top_line() + 1);
             }
 
-            if self.buffer.get(self.current_line) != None
-                && self.current_char > self.buffer.get(self.current_line).unwrap().len()
-            {
-                self.current_char = self.buffer.get(self.current_line).unwrap().len() + 1;
-                // TODO make + 4 variable depending on the length of the line numbers
-                self.x = (self.buffer.get(self.current_line).unwrap().len() + 1 + 4) as u16;
-            }
+            self.current_char = cmp::min(self.current_char, self.current_line_len() + 1);
+            self.sync_x();
         }
     }
 
     /// Used to move the cursor to a new line (both the on-screen and the internal buffer cursor).
     pub fn move_cursor_new_line(&mut self) {
         // TODO current_line and scrolling handling
+        self.current_char = 1;
+        self.x = self.start_x();
+
         if self.y >= self.height - 3 {
-            self.x = self.start_x();
             self.set_top_line(self.top_line() + 1);
         } else {
-            self.current_char = 1;
-            self.x = self.start_x();
             self.y += 1;
         }
     }
 
     /// Used to move the cursor to the end of the current line (both the on-screen and the internal buffer cursor).
     pub fn move_cursor_eocl(&mut self) {
-        // TODO make + 4 variable depending on the length of the line numbers
-        self.current_char = self.buffer.get(self.current_line).unwrap().len() + 1;
-        self.x = (self.buffer.get(self.current_line).unwrap().len() + 1 + 4) as u16;
+        self.current_char = self.current_line_len() + 1;
+        self.sync_x();
         self.y = (self.current_line - self.top_line + 1) as u16;
     }
 
+    /// Moves the cursor back onto the last character if it is behind it, where it can only be in
+    /// Insert mode.
+    pub fn clamp_cursor(&mut self) {
+        let last = cmp::max(self.current_line_len(), 1);
+
+        if self.current_char > last {
+            self.current_char = last;
+            self.sync_x();
+        }
+    }
+
+    /// Gets the number of lines of text that fit on the screen.
+    pub fn text_height(&self) -> usize {
+        cmp::max(self.height as usize, 4) - 3
+    }
+
+    /// Moves the cursor to a line and (1-based) grapheme, both limited to the buffer. Scrolls as
+    /// little as possible to keep the cursor visible.
+    pub fn set_cursor(&mut self, line_number: usize, char: usize) {
+        let line_number = line_number.clamp(1, self.buffer.len());
+        let text_height = self.text_height();
+
+        if line_number < self.top_line {
+            self.set_top_line(line_number);
+        } else if line_number >= self.top_line + text_height {
+            self.set_top_line(line_number + 1 - text_height);
+        }
+
+        self.current_line = line_number;
+        self.current_char = char.clamp(1, self.current_line_len() + 1);
+        self.y = (self.current_line - self.top_line + 1) as u16;
+        self.sync_x();
+    }
+
+    /// Recalculates the on-screen x-position of the cursor from the internal buffer cursor.
+    pub fn sync_x(&mut self) {
+        let line = self.buffer.get(self.current_line).unwrap();
+        let column = util::display_column(line, self.current_char - 1, self.tab_width());
+
+        self.x = self.start_x() + column as u16;
+    }
+
+    /// Gets the length of the current line in graphemes.
+    pub fn current_line_len(&self) -> usize {
+        util::grapheme_count(self.buffer.get(self.current_line).unwrap())
+    }
+
+    /// Inserts text at the cursor position and moves the cursor behind it.
+    pub fn insert_at_cursor(&mut self, text: &str) -> Result<()> {
+        let line = self.buffer.line_mut(self.current_line)?;
+        let index = util::byte_index(line, self.current_char - 1);
+
+        line.insert_str(index, text);
+
+        self.current_char += util::grapheme_count(text);
+        self.sync_x();
+        self.modified = true;
+
+        Ok(())
+    }
+
+    /// Inserts text that may span several lines at the cursor position.
+    pub fn insert_text(&mut self, text: &str) -> Result<()> {
+        for (i, part) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.split_line()?;
+            }
+
+            self.insert_at_cursor(part)?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves the part of the current line right of the cursor to a new line below it.
+    pub fn split_line(&mut self) -> Result<()> {
+        let current_line = self.buffer.line_mut(self.current_line)?;
+        let index = util::byte_index(current_line, self.current_char - 1);
+        let to_next_line = current_line.split_off(index);
+
+        self.buffer.insert(self.current_line + 1, to_next_line)?;
+
+        self.current_line += 1;
+        self.move_cursor_new_line();
+        self.modified = true;
+
+        Ok(())
+    }
+
+    /// Appends the current line to the previous one, with the cursor where they meet.
+    pub fn join_with_previous(&mut self) -> Result<()> {
+        if self.current_line == 1 {
+            return Ok(());
+        }
+
+        let len = self.buffer.join(self.current_line - 1, "")?;
+        let line = self.buffer.line(self.current_line - 1)?;
+        let char = util::grapheme_count(&line[..len]) + 1;
+
+        self.set_cursor(self.current_line - 1, char);
+        self.modified = true;
+
+        Ok(())
+    }
+
+    /// Deletes the grapheme under the cursor, at the end of the line the next line is joined.
+    pub fn delete_at_cursor(&mut self) -> Result<()> {
+        if self.current_char > self.current_line_len() {
+            if self.current_line < self.buffer.len() {
+                self.buffer.join(self.current_line, "")?;
+                self.modified = true;
+            }
+
+            return Ok(());
+        }
+
+        self.delete_range(self.current_char, self.current_char + 1)
+    }
+
+    /// Deletes the graphemes of the current line from `from` up to (not including) `to`, both
+    /// 1-based, and puts the cursor where they were.
+    pub fn delete_range(&mut self, from: usize, to: usize) -> Result<()> {
+        let line = self.buffer.line_mut(self.current_line)?;
+        let range = util::byte_index(line, from - 1)..util::byte_index(line, to - 1);
+
+        if range.is_empty() {
+            return Ok(());
+        }
+
+        line.replace_range(range, "");
+
+        self.current_char = from;
+        self.sync_x();
+        self.modified = true;
+
+        Ok(())
+    }
+
+    /// Deletes the graphemes of the current line from `from` up to (not including) `to` like
+    /// `delete_range`, but keeps them in the registers (`x`).
+    pub fn delete_into_register(&mut self, from: usize, to: usize) -> Result<()> {
+        let line = self.buffer.line(self.current_line)?;
+        let text = &line[util::byte_index(line, from - 1)..util::byte_index(line, to - 1)];
+
+        if !text.is_empty() {
+            let register = Register::new(vec![String::from(text)], false);
+            self.registers.delete(register);
+        }
+
+        self.delete_range(from, to)
+    }
+
+    /// Overwrites the grapheme under the cursor in Replace mode, at the end of the line the
+    /// character is appended instead.
+    pub fn replace_at_cursor(&mut self, c: char) -> Result<()> {
+        let line = self.buffer.line(self.current_line)?;
+        let old = if self.current_char <= self.current_line_len() {
+            let range = util::byte_index(line, self.current_char - 1)
+                ..util::byte_index(line, self.current_char);
+
+            Some(String::from(&line[range]))
+        } else {
+            None
+        };
+
+        if old.is_some() {
+            self.delete_range(self.current_char, self.current_char + 1)?;
+        }
+
+        self.insert_at_cursor(&c.to_string())?;
+        self.replaced.push(old);
+
+        Ok(())
+    }
+
+    /// Undoes the last overwrite of Replace mode (`Backspace`), or only moves the cursor left if
+    /// nothing was overwritten.
+    pub fn restore_replaced(&mut self) -> Result<()> {
+        if self.current_char == 1 {
+            self.replaced.clear();
+            return Ok(());
+        }
+
+        match self.replaced.pop() {
+            Some(Some(old)) => {
+                self.delete_range(self.current_char - 1, self.current_char)?;
+                self.insert_at_cursor(&old)?;
+                self.move_cursor_left();
+            }
+            Some(None) => self.delete_range(self.current_char - 1, self.current_char)?,
+            None => self.move_cursor_left(),
+        }
+
+        Ok(())
+    }
+
+    /// Replaces `count` graphemes starting at the cursor with a character (`r`). A line break
+    /// replaces all of them with a single one. Nothing happens if the line is too short.
+    pub fn replace_chars(&mut self, c: char, count: usize) -> Result<()> {
+        if self.current_char + count - 1 > self.current_line_len() {
+            return Ok(());
+        }
+
+        self.delete_range(self.current_char, self.current_char + count)?;
+
+        if c == '\n' {
+            self.split_line()?;
+            return self.auto_indent();
+        }
+
+        self.insert_at_cursor(&c.to_string().repeat(count))?;
+        self.move_cursor_left();
+
+        Ok(())
+    }
+
+    /// Switches upper and lower case of `count` graphemes starting at the cursor (`~`), the cursor
+    /// moves behind them but stays on the line.
+    pub fn toggle_case(&mut self, count: usize) -> Result<()> {
+        let len = self.current_line_len();
+
+        if len == 0 {
+            return Ok(());
+        }
+
+        let to = cmp::min(self.current_char + count, len + 1);
+        let line = self.buffer.line_mut(self.current_line)?;
+        let range = util::byte_index(line, self.current_char - 1)..util::byte_index(line, to - 1);
+        let toggled: String = line[range.clone()]
+            .chars()
+            .flat_map(|c| {
+                if c.is_lowercase() {
+                    c.to_uppercase().collect::<Vec<_>>()
+                } else {
+                    c.to_lowercase().collect()
+                }
+            })
+            .collect();
+
+        line.replace_range(range, &toggled);
+
+        self.current_char = cmp::min(to, len);
+        self.sync_x();
+        self.modified = true;
+
+        Ok(())
+    }
+
+    /// Joins `count` lines, but at least two, starting at the cursor line (`J`). The leading
+    /// whitespace of the joined lines is replaced by a single space.
+    pub fn join_lines(&mut self, count: usize) -> Result<()> {
+        let joins = cmp::min(
+            cmp::max(count, 2) - 1,
+            self.buffer.len() - self.current_line,
+        );
+        let mut char = self.current_char;
+
+        for _ in 0..joins {
+            let next = String::from(self.buffer.line(self.current_line + 1)?.trim_start());
+            let line = self.buffer.line(self.current_line)?;
+            let separator = if line.is_empty()
+                || line.ends_with(char::is_whitespace)
+                || next.is_empty()
+                || next.starts_with(')')
+            {
+                ""
+            } else {
+                " "
+            };
+
+            self.buffer.set(self.current_line + 1, next)?;
+
+            let len = self.buffer.join(self.current_line, separator)?;
+            let line = self.buffer.line(self.current_line)?;
+
+            char = util::grapheme_count(&line[..len]) + 1;
+            self.modified = true;
+            self.top_line_changed = true;
+        }
+
+        self.set_cursor(self.current_line, char);
+
+        Ok(())
+    }
+
+    /// Opens a new, indented line below or above the cursor line and moves the cursor there
+    /// (`o` and `O`).
+    pub fn open_line(&mut self, below: bool) -> Result<()> {
+        let line_number = self.current_line + below as usize;
+
+        self.buffer.insert(line_number, String::new())?;
+        self.set_cursor(line_number, 1);
+        self.modified = true;
+
+        self.auto_indent()
+    }
+
+    /// Deletes whole lines and returns them, the buffer keeps at least one empty line. The cursor
+    /// goes to the first non-blank of the line after them.
+    pub fn delete_lines(&mut self, from: usize, to: usize) -> Result<Vec<String>> {
+        let mut lines = Vec::new();
+
+        for _ in from..=to {
+            lines.push(self.buffer.remove(from)?);
+        }
+
+        if self.buffer.is_empty() {
+            self.buffer.push(String::new());
+        }
+
+        let line_number = cmp::min(from, self.buffer.len());
+
+        self.set_cursor(line_number, self.first_non_blank(line_number));
+        self.modified = true;
+        self.top_line_changed = true;
+
+        Ok(lines)
+    }
+
+    /// Puts the unnamed register `count` times (`p` and `P`). Whole lines go below or above the
+    /// cursor line, other text after or before the cursor.
+    pub fn put(&mut self, before: bool, count: usize) -> Result<()> {
+        let register = self
+            .registers
+            .get('"')
+            .cloned()
+            .ok_or(Error::EmptyRegister('"'))?;
+
+        if register.linewise {
+            let line_number = self.current_line + !before as usize;
+            let lines = register
+                .lines
+                .iter()
+                .cycle()
+                .take(register.lines.len() * count);
+
+            for (i, line) in lines.enumerate() {
+                self.buffer.insert(line_number + i, line.clone())?;
+            }
+
+            self.set_cursor(line_number, self.first_non_blank(line_number));
+            self.modified = true;
+            self.top_line_changed = true;
+
+            return Ok(());
+        }
+
+        if !before {
+            self.move_cursor_right();
+        }
+
+        self.insert_text(&register.text().repeat(count))?;
+
+        // The cursor ends up on the last character that was put
+        self.move_cursor_left();
+
+        Ok(())
+    }
+
+    /// Gets the (1-based) grapheme index of the first non-blank character of a line.
+    pub fn first_non_blank(&self, line_number: usize) -> usize {
+        let line = self.buffer.get(line_number).map_or("", String::as_str);
+
+        util::grapheme_count(util::indentation(line)) + 1
+    }
+
+    /// Inserts a tab at the cursor position. Depending on the tab type this is either a hard tab
+    /// or as many spaces as needed to reach the next tab (or soft tab) stop.
+    pub fn insert_tab(&mut self) -> Result<()> {
+        match self.tab_type() {
+            TabType::Hard => self.insert_at_cursor("\t"),
+            TabType::Soft => {
+                let line = self.buffer.line(self.current_line)?;
+                let column = util::display_column(line, self.current_char - 1, self.tab_width());
+                let spaces = util::tab_stop_width(column, self.soft_tab_width());
+
+                self.insert_at_cursor(&" ".repeat(spaces))
+            }
+        }
+    }
+
+    /// Deletes the grapheme before the cursor. Spaces of soft tabs are deleted up to the previous
+    /// soft tab stop, indentation is deleted a whole indent level at a time.
+    pub fn delete_before_cursor(&mut self) -> Result<()> {
+        if self.current_char == 1 {
+            return Ok(());
+        }
+
+        let line = self.buffer.line(self.current_line)?;
+        let before = &line[..util::byte_index(line, self.current_char - 1)];
+        let spaces = before.len() - before.trim_end_matches(' ').len();
+
+        let mut count = 1;
+
+        if spaces > 0 && self.tab_type() == TabType::Soft {
+            let step = if self.option("softtabstop").as_int().unwrap() > 0 {
+                self.soft_tab_width()
+            } else if util::indentation(line).len() >= before.len() {
+                self.shift_width()
+            } else {
+                1
+            };
+            let column = util::display_column(line, self.current_char - 1, self.tab_width());
+
+            count = cmp::min(spaces, (column - 1) % step + 1);
+        }
+
+        let line = self.buffer.line_mut(self.current_line)?;
+        let from = util::byte_index(line, self.current_char - 1 - count);
+        let to = util::byte_index(line, self.current_char - 1);
+
+        line.replace_range(from..to, "");
+
+        self.current_char -= count;
+        self.sync_x();
+        self.modified = true;
+
+        Ok(())
+    }
+
+    /// Builds the whitespace for an indentation of the given width, using tabs or spaces
+    /// depending on the tab type.
+    pub fn indent_string(&self, width: usize) -> String {
+        match self.tab_type() {
+            TabType::Soft => " ".repeat(width),
+            TabType::Hard => {
+                let tab_width = self.tab_width();
+
+                "\t".repeat(width / tab_width) + &" ".repeat(width % tab_width)
+            }
+        }
+    }
+
+    /// Changes the indentation of a line by the given number of shiftwidth levels.
+    pub fn shift_line(&mut self, line_number: usize, levels: isize) -> Result<()> {
+        let shift_width = self.shift_width();
+        let line = self.buffer.line(line_number)?;
+        let width = util::indent_width(line, self.tab_width());
+
+        let new_width = if levels >= 0 {
+            (width / shift_width + levels as usize) * shift_width
+        } else {
+            let current_levels = width.div_ceil(shift_width);
+
+            current_levels.saturating_sub(levels.unsigned_abs()) * shift_width
+        };
+
+        self.set_indent(line_number, new_width)
+    }
+
+    /// Replaces the indentation of a line by one of the given width.
+    pub fn set_indent(&mut self, line_number: usize, width: usize) -> Result<()> {
+        let indentation = self.indent_string(width);
+        let line = self.buffer.line_mut(line_number)?;
+        let old_len = util::indentation(line).len();
+
+        if line[..old_len] == indentation {
+            return Ok(());
+        }
+
+        line.replace_range(..old_len, &indentation);
+
+        if line_number == self.current_line {
+            let new_char =
+                self.current_char as isize + indentation.len() as isize - old_len as isize;
+
+            self.current_char = cmp::max(new_char, 1) as usize;
+            self.sync_x();
+        }
+
+        self.modified = true;
+
+        Ok(())
+    }
+
+    /// Gets the width of one indent level, falling back to the tab width if `shiftwidth` is zero.
+    pub fn shift_width(&self) -> usize {
+        match self.option("shiftwidth").as_int().unwrap() {
+            0 => self.tab_width(),
+            width => width,
+        }
+    }
+
+    /// Gets the width of a soft tab, falling back to the tab width if `softtabstop` is zero.
+    pub fn soft_tab_width(&self) -> usize {
+        match self.option("softtabstop").as_int().unwrap() {
+            0 => self.tab_width(),
+            width => width,
+        }
+    }
+
+    /// Opens a file in the buffer. If there is a swap file for it, the user is asked whether to
+    /// recover from it first. A file that does not exist yet gives an empty buffer.
+    pub fn open(&mut self, file_name: &str, encoding: Option<&str>) -> Result<()> {
+        let previous_name = mem::replace(&mut self.file_name, String::from(file_name));
+        let previous_swap = self.swap_file.take();
+
+        // Scripts neither write swap files nor can answer questions about them
+        let choice = if self.headless {
+            None
+        } else {
+            self.check_swap()
+        };
+
+        if choice == Some(SwapChoice::Abort) {
+            self.file_name = previous_name;
+            self.swap_file = previous_swap;
+
+            return Ok(());
+        }
+
+        match self.load_encoded(encoding) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                self.load_new();
+                self.show_message(&format!("\"{}\" [New]", file_name));
+            }
+            Err(err) => {
+                self.file_name = previous_name;
+                self.swap_file = previous_swap;
+
+                return Err(Error::Read(String::from(file_name), err));
+            }
+        }
+
+        if let Some(path) = previous_swap {
+            let _ = fs::remove_file(path);
+        }
+
+        match choice {
+            Some(SwapChoice::Recover(swap_file, replace)) => self.recover_swap(swap_file, replace),
+            Some(SwapChoice::ReadOnly) => {
+                self.buffer
+                    .options
+                    .set_by_name("readonly", Value::Bool(true));
+            }
+            _ => {}
+        }
+
+        if self.read_only_mode {
+            self.buffer
+                .options
+                .set_by_name("readonly", Value::Bool(true));
+        }
+
+        Ok(())
+    }
+
+    /// Loads the file again, discarding all changes but keeping the cursor where it was.
+    pub fn reload(&mut self, encoding: Option<&str>) -> Result<()> {
+        let (line, char) = (self.current_line, self.current_char);
+        let top_line = self.top_line;
+
+        self.load_encoded(encoding)
+            .map_err(|err| Error::Read(self.file_name.clone(), err))?;
+        self.remove_swap();
+
+        self.current_line = line.clamp(1, self.buffer.len());
+        self.current_char = char.clamp(1, self.current_line_len() + 1);
+        self.set_top_line(cmp::min(top_line, self.current_line));
+        self.y = (self.current_line - self.top_line + 1) as u16;
+        self.sync_x();
+
+        Ok(())
+    }
+
+    /// Asks the user a question in the message line and waits for one of the given answers.
+    /// Pressing escape chooses the last answer, as does running without a terminal.
+    pub fn prompt(&mut self, question: &str, answers: &[char]) -> char {
+        self.show_message(question);
+
+        #[cfg(feature = "terminal")]
+        while let Some(key) = self.input.next_key() {
+            match key {
+                Key::Char(c) if answers.contains(&c.to_ascii_lowercase()) => {
+                    return c.to_ascii_lowercase();
+                }
+                Key::Esc => break,
+                _ => {}
+            }
+        }
+
+        *answers.last().unwrap()
+    }
+
+    /// Reads an Ex command from the command line and runs it. Besides typing, the command line
+    /// can be edited with the arrow keys, `Home`/`End`, `Ctrl-w` and `Ctrl-u`. `Up` and `Down` go
+    /// through the history of commands starting with the typed text, `Tab` completes the word
+    /// before the cursor and `Ctrl-r {reg}` inserts a register.
+    #[cfg(feature = "terminal")]
     pub fn read_command(&mut self) {
-        let stdin = stdin();
-        let mut stdout = stdout().into_raw_mode().unwrap();
+        // The command line takes the place of the message
+        self.message = None;
 
-        self.draw_bar_empty();
+        let mut line = CommandLine::new();
+        // The text the history was searched with and the entry that is shown
+        let mut history: Option<(String, usize)> = None;
+        // Where the completed word starts, the candidates and the one that is shown
+        let mut completion: Option<(usize, Vec<String>, usize)> = None;
 
-        write!(
-            stdout,
-            "{}{}{}:",
-            color::Fg(color::Black),
-            color::Bg(color::Rgb(0xcb, 0xb5, 0x25)),
-            termion::cursor::Goto(1, self.height - 1)
-        )
-        .unwrap();
-        stdout.flush().unwrap();
+        self.draw_command_line(&line);
 
-        let mut cmd = String::new();
+        while let Some(key) = self.next_mapped_key(MapMode::CommandLine) {
+            if !matches!(key, Key::Up | Key::Down) {
+                history = None;
+            }
+
+            if !matches!(key, Key::Char('\t') | Key::Ctrl('n') | Key::Ctrl('p'))
+                && completion.take().is_some()
+            {
+                self.draw_bar();
+            }
 
-        for c in stdin.keys() {
-            match c.unwrap() {
-                Key::Char('\n') => {
-                    break;
+            match key {
+                Key::Char('\n') => break,
+                Key::Esc => {
+                    self.draw_message();
+                    return;
+                }
+                // Deleting the `:` leaves the command line
+                Key::Backspace if line.text.is_empty() => {
+                    self.draw_message();
+                    return;
                 }
-                Key::Char(c) => {
-                    cmd.push(c);
+                Key::Backspace => line.backspace(),
+                Key::Delete => line.delete(),
+                Key::Left => line.left(),
+                Key::Right => line.right(),
+                Key::Home | Key::Ctrl('b') => line.home(),
+                Key::End | Key::Ctrl('e') => line.end(),
+                Key::Ctrl('w') => line.delete_word(),
+                Key::Ctrl('u') => line.delete_to_start(),
+                Key::Up => {
+                    let (prefix, index) =
+                        history.get_or_insert((line.text.clone(), self.command_history.len()));
 
-                    write!(stdout, "{}", c).unwrap();
-                    stdout.flush().unwrap();
+                    if let Some(found) = self.command_history.previous(prefix, *index) {
+                        *index = found;
+                        line.set(self.command_history.get(found).unwrap());
+                    }
                 }
-                Key::Backspace => {
-                    if !cmd.is_empty() {
-                        cmd.pop();
-
-                        write!(
-                            stdout,
-                            "{}{}:{}",
-                            termion::clear::CurrentLine,
-                            termion::cursor::Goto(1, self.height - 1),
-                            cmd
-                        )
-                        .unwrap();
-                        stdout.flush().unwrap();
+                Key::Down => {
+                    if let Some((prefix, index)) = &mut history {
+                        match self.command_history.next(prefix, *index) {
+                            Some(found) => {
+                                *index = found;
+                                line.set(self.command_history.get(found).unwrap());
+                            }
+                            None => {
+                                *index = self.command_history.len();
+                                line.set(prefix);
+                            }
+                        }
                     }
                 }
-                Key::Esc => {
-                    return;
+                Key::Char('\t') | Key::Ctrl('n') | Key::Ctrl('p') => {
+                    let (start, candidates, index) = match completion.take() {
+                        Some((start, candidates, index)) => {
+                            let index = if key == Key::Ctrl('p') {
+                                (index + candidates.len() - 1) % candidates.len()
+                            } else {
+                                (index + 1) % candidates.len()
+                            };
+
+                            (start, candidates, index)
+                        }
+                        None => {
+                            let (start, candidates) =
+                                cmdline::complete(line.before_cursor(), &self.arglist);
+
+                            if candidates.is_empty() || key != Key::Char('\t') {
+                                self.draw_command_line(&line);
+                                continue;
+                            }
+
+                            (start, candidates, 0)
+                        }
+                    };
+
+                    line.replace_before_cursor(start, &candidates[index]);
+
+                    // A single candidate is simply taken
+                    if candidates.len() > 1 {
+                        self.draw_wildmenu(&candidates, index);
+                        completion = Some((start, candidates, index));
+                    }
                 }
+                // The register name is taken as it is typed
+                Key::Ctrl('r') => {
+                    if let Some(Key::Char(name)) = self.next_unmapped_key() {
+                        if let Some(text) = self.register_text(name) {
+                            line.insert(&text.replace('\n', " "));
+                        }
+                    }
+                }
+                Key::Char(c) => line.insert(&c.to_string()),
                 _ => {}
             }
+
+            self.draw_command_line(&line);
+        }
+
+        self.command_history.size = self.option("history").as_int().unwrap();
+        self.command_history.add(&line.text);
+        self.registers
+            .set(':', Register::new(vec![line.text.clone()], false));
+
+        self.run_command(&line.text);
+    }
+
+    /// Gets the next event, with mappings of the given mode applied to the keys. Ex commands
+    /// that keys are mapped to are run right away.
+    #[cfg(feature = "terminal")]
+    pub fn next_event(&mut self, mode: MapMode) -> Event {
+        let mut timed_out = false;
+
+        loop {
+            let wait = match self.keymap.resolve(mode, &mut self.typeahead, timed_out) {
+                Ok(Some(Resolved::Key(key))) => return Event::Key(key),
+                Ok(Some(Resolved::Command(command))) => {
+                    self.run_command(&command);
+
+                    if !self.running {
+                        return Event::Closed;
+                    }
+
+                    self.draw();
+                    timed_out = false;
+
+                    continue;
+                }
+                // The keys typed so far might be the start of a mapping
+                Ok(Some(Resolved::Wait)) if self.option("timeout").as_bool().unwrap() => {
+                    Some(self.option("timeoutlen").as_int().unwrap())
+                }
+                Ok(Some(Resolved::Wait)) => None,
+                Ok(None) => Some(self.option("updatetime").as_int().unwrap()),
+                Err(err) => {
+                    self.show_error(&err.to_string());
+                    continue;
+                }
+            };
+            let waiting = !self.typeahead.is_empty();
+
+            match self
+                .input
+                .next(wait.map(|ms| Duration::from_millis(ms as u64)))
+            {
+                Event::Key(key) => self.typeahead.push_back((key, true)),
+                Event::Idle if waiting => timed_out = true,
+                event => return event,
+            }
+        }
+    }
+
+    /// Gets the next event without applying any mappings, for keys that are an argument of a
+    /// command.
+    #[cfg(feature = "terminal")]
+    pub fn next_unmapped_event(&mut self) -> Event {
+        match self.typeahead.pop_front() {
+            Some((key, _)) => Event::Key(key),
+            None => {
+                let wait = self.option("updatetime").as_int().unwrap();
+
+                self.input.next(Some(Duration::from_millis(wait as u64)))
+            }
+        }
+    }
+
+    /// Gets the next key, with mappings of the given mode applied.
+    #[cfg(feature = "terminal")]
+    pub fn next_mapped_key(&mut self, mode: MapMode) -> Option<Key> {
+        loop {
+            match self.next_event(mode) {
+                Event::Key(key) => return Some(key),
+                Event::Closed => return None,
+                _ => {}
+            }
+        }
+    }
+
+    /// Gets the next key without applying any mappings.
+    #[cfg(feature = "terminal")]
+    pub fn next_unmapped_key(&mut self) -> Option<Key> {
+        loop {
+            match self.next_unmapped_event() {
+                Event::Key(key) => return Some(key),
+                Event::Closed => return None,
+                _ => {}
+            }
+        }
+    }
+
+    /// Applies the arguments of a mapping command like `:nmap` to the given modes. Without a
+    /// right-hand side, the mappings starting with the given keys are listed instead.
+    pub fn map_keys(
+        &mut self,
+        command: MapCommand,
+        modes: &[MapMode],
+        args: &[&str],
+    ) -> Result<()> {
+        let leader = self.option("mapleader").to_string();
+
+        match (command, args) {
+            (MapCommand::Unmap, [lhs]) => {
+                let lhs = keymap::parse_keys(lhs, &leader);
+                let mut found = false;
+
+                for mode in modes {
+                    found |= self.keymap.unmap(*mode, &lhs);
+                }
+
+                if !found {
+                    return Err(Error::InvalidArgument(String::from("No such mapping")));
+                }
+            }
+            (MapCommand::Unmap, _) => {
+                return Err(Error::InvalidArgument(String::from("Argument required")));
+            }
+            (_, []) | (_, [_]) => {
+                let prefix = args
+                    .first()
+                    .map(|lhs| keymap::parse_keys(lhs, &leader))
+                    .unwrap_or_default();
+                let lines: Vec<String> = modes
+                    .iter()
+                    .flat_map(|mode| self.keymap.find(*mode, &prefix))
+                    .map(Mapping::describe)
+                    .collect();
+
+                if lines.is_empty() {
+                    self.show_message("No mapping found");
+                } else {
+                    self.show_lines(&lines);
+                }
+            }
+            (_, [lhs, rhs @ ..]) => {
+                let lhs = keymap::parse_keys(lhs, &leader);
+                let rhs = keymap::parse_rhs(&rhs.join(" "), &leader);
+
+                for mode in modes {
+                    self.keymap.map(Mapping {
+                        mode: *mode,
+                        lhs: lhs.clone(),
+                        rhs: rhs.clone(),
+                        noremap: command == MapCommand::NoRemap,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Gets the content of a register, `%` is the name of the current file.
+    pub fn register_text(&self, name: char) -> Option<String> {
+        match name {
+            '%' if !self.file_name.is_empty() => Some(self.file_name.clone()),
+            _ => self.registers.get(name).map(Register::text),
+        }
+    }
+
+    /// Runs an Ex command, showing errors in the message line.
+    pub fn run_command(&mut self, cmd: &str) {
+        let cmd = cmd.trim_start_matches(':');
+
+        // The shell gets the command as it was typed, spaces and all
+        if let Some(shell_cmd) = cmd.strip_prefix('!') {
+            if let Err(err) = self.shell(shell_cmd) {
+                self.show_error(&err.to_string());
+            }
+
+            return;
         }
 
         let cmd_parts: Vec<&str> = cmd.split_whitespace().collect();
 
-        self.handle_command(cmd_parts);
+        if let Err(err) = self.handle_command(cmd_parts) {
+            self.show_error(&err.to_string());
+        }
+    }
+
+    /// Runs the Ex commands in a config file.
+    pub fn source(&mut self, file_name: &str) -> Result<()> {
+        let text = fs::read_to_string(util::expand_home(file_name))
+            .map_err(|err| Error::Read(String::from(file_name), err))?;
+
+        self.run_script(&text);
+
+        Ok(())
+    }
+
+    /// Runs Ex commands, one per line, until one of them quits. Empty lines and lines starting
+    /// with `"` are skipped.
+    pub fn run_script(&mut self, text: &str) {
+        for line in text.lines().map(str::trim) {
+            if !self.running {
+                break;
+            }
+
+            if !line.is_empty() && !line.starts_with('"') {
+                self.run_command(line);
+            }
+        }
+    }
+
+    /// Sets everything up as asked for on the command line.
+    pub fn start(&mut self, args: Args, stdin: Option<Vec<u8>>) {
+        match args.config.as_deref() {
+            Some("NONE") => {}
+            // Scripts only use a config file if it is given explicitly
+            None if self.headless => {}
+            Some(config) => {
+                if let Err(err) = self.source(config) {
+                    self.show_error(&err.to_string());
+                }
+            }
+            None => {
+                if util::expand_home(CONFIG_FILE).exists() {
+                    let _ = self.source(CONFIG_FILE);
+                }
+            }
+        }
+
+        // The history is read once the config has set how much of it is kept, and there might not
+        // be one yet
+        #[cfg(feature = "terminal")]
+        if !self.headless {
+            self.command_history.size = self.option("history").as_int().unwrap();
+            let _ = self.command_history.load(HISTORY_FILE);
+        }
+
+        self.read_only_mode = args.read_only;
+
+        if let Some(bytes) = stdin {
+            match self.load_bytes(&bytes, None) {
+                // The content is not saved anywhere yet
+                Ok(()) => self.modified = true,
+                Err(err) => self.show_error(&Error::Read(String::from("-"), err).to_string()),
+            }
+
+            if self.read_only_mode {
+                self.buffer
+                    .options
+                    .set_by_name("readonly", Value::Bool(true));
+            }
+        }
+
+        self.arglist = args.files;
+
+        if let Some(file_name) = self.arglist.first().cloned() {
+            if let Err(err) = self.open(&file_name, None) {
+                self.show_error(&err.to_string());
+            }
+        }
+
+        match args.jump {
+            Some(Jump::Line(line)) => self.goto_line(line),
+            Some(Jump::LastLine) => self.goto_line(self.buffer.len()),
+            Some(Jump::Pattern(pattern)) => {
+                match self.buffer.iter().position(|line| line.contains(&pattern)) {
+                    Some(index) => self.goto_line(index + 1),
+                    None => self.show_error(&format!("Pattern not found: {}", pattern)),
+                }
+            }
+            None => {}
+        }
+
+        for command in args.commands {
+            self.run_command(&command);
+        }
+    }
+
+    /// Moves the cursor to the start of a line, scrolling so that it is visible.
+    pub fn goto_line(&mut self, line_number: usize) {
+        let line_number = line_number.clamp(1, self.buffer.len());
+        let text_height = cmp::max(self.height as usize, 3) - 2;
+
+        if line_number < self.top_line || line_number >= self.top_line + text_height {
+            self.set_top_line(cmp::max(line_number.saturating_sub(text_height / 2), 1));
+        }
+
+        self.current_line = line_number;
+        self.current_char = 1;
+        self.y = (self.current_line - self.top_line + 1) as u16;
+        self.sync_x();
     }
 
     /// Find out the x-position in the terminal where the first character of
@@ -261,9 +1270,215 @@ impl Editor {
         (self.buffer.len() as f32 + 1.).log10() as u16 + 3
     }
 
+    /// Shows a message in the message line and keeps it in the history. Without a terminal,
+    /// warnings and errors go to stderr and other messages are dropped.
+    pub fn show(&mut self, severity: Severity, text: &str) {
+        let message = Message::new(severity, text);
+
+        if severity == Severity::Error {
+            self.error_count += 1;
+        }
+
+        self.messages.push(message.clone());
+
+        if self.headless || cfg!(not(feature = "terminal")) {
+            if severity != Severity::Info {
+                eprintln!("rydl: {}", text);
+            }
+        } else {
+            self.message = Some(message);
+
+            #[cfg(feature = "terminal")]
+            self.draw_message();
+        }
+    }
+
     pub fn show_error(&mut self, msg: &str) {
-        self.draw_bar_text(String::from(msg), color::Rgb(0xf4, 0x59, 0x05));
+        self.show(Severity::Error, msg);
+    }
+
+    pub fn show_warning(&mut self, msg: &str) {
+        self.show(Severity::Warning, msg);
+    }
+
+    pub fn show_message(&mut self, msg: &str) {
+        self.show(Severity::Info, msg);
+    }
+
+    /// Shows the output of a command. A single line goes to the message line, more lines are
+    /// shown in a pager. Without a terminal the output goes to stdout.
+    pub fn show_lines(&mut self, lines: &[String]) {
+        if self.headless || cfg!(not(feature = "terminal")) {
+            for line in lines {
+                println!("{}", line);
+            }
+        } else if lines.len() > 1 {
+            #[cfg(feature = "terminal")]
+            self.page(lines);
+        } else {
+            let text = lines.first().map_or("", String::as_str);
+            self.message = Some(Message::new(Severity::Info, text));
+
+            #[cfg(feature = "terminal")]
+            self.draw_message();
+        }
+    }
+
+    /// Shows lines a screen at a time, then waits for a key. A `:` starts a new command line
+    /// right away.
+    #[cfg(feature = "terminal")]
+    fn page(&mut self, lines: &[String]) {
+        let mut stdout = stdout().into_raw_mode().unwrap();
+        let rows = cmp::max(self.height as usize, 2) - 1;
+        let mut last_key = None;
+
+        for (i, page) in lines.chunks(rows).enumerate() {
+            write!(stdout, "{}", termion::clear::All).unwrap();
+
+            for (y, line) in (1..).zip(page) {
+                let line: String = util::expand_tabs(line, 8)
+                    .chars()
+                    .take(self.width as usize)
+                    .collect();
+
+                write!(stdout, "{}{}", termion::cursor::Goto(1, y), line).unwrap();
+            }
+
+            let more = (i + 1) * rows < lines.len();
+            let prompt = if more {
+                "-- More --"
+            } else {
+                "Press ENTER or type command to continue"
+            };
+
+            write!(
+                stdout,
+                "{}{}{}{}",
+                termion::cursor::Goto(1, self.height),
+                color::Fg(color::Rgb(0xcb, 0xb5, 0x25)),
+                prompt,
+                color::Fg(color::Reset)
+            )
+            .unwrap();
+            stdout.flush().unwrap();
+
+            last_key = self.input.next_key();
+
+            if more && matches!(last_key, Some(Key::Char('q')) | Some(Key::Esc) | None) {
+                break;
+            }
+        }
+
+        self.redraw();
+
+        if last_key == Some(Key::Char(':')) {
+            self.read_command();
+        }
+    }
+
+    /// Runs a shell command and shows its output.
+    pub fn shell(&mut self, command: &str) -> Result<()> {
+        let output = Command::new("sh")
+            .arg("-c")
+            // Keep the order of stdout and stderr lines
+            .arg(format!("exec 2>&1\n{}", command))
+            .stdin(Stdio::null())
+            .output()?;
+
+        let mut lines: Vec<String> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(String::from)
+            .collect();
+
+        if let Some(code) = output.status.code().filter(|&code| code != 0) {
+            lines.push(format!("shell returned {}", code));
+        }
+
+        self.show_lines(&lines);
+
+        Ok(())
+    }
+
+    /// Gets the effective value of an option for the current buffer and window.
+    pub fn option(&self, name: &str) -> Value {
+        self.settings
+            .get(&self.buffer.options, name)
+            .expect("Unknown option")
+    }
+
+    pub fn tab_width(&self) -> usize {
+        cmp::max(self.option("tabstop").as_int().unwrap(), 1)
+    }
+
+    pub fn tab_type(&self) -> TabType {
+        if self.option("expandtab").as_bool().unwrap() {
+            TabType::Soft
+        } else {
+            TabType::Hard
+        }
+    }
+
+    /// Checks whether the buffer may be changed.
+    pub fn check_modifiable(&self) -> Result<()> {
+        if !self.option("modifiable").as_bool().unwrap() {
+            Err(Error::NotModifiable)
+        } else if self.option("readonly").as_bool().unwrap() {
+            Err(Error::ChangeReadOnly)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Applies the arguments of a `:set` or `:setlocal` command.
+    pub fn set_options(&mut self, args: &[&str], local_only: bool) -> Result<()> {
+        if args.is_empty() {
+            let changed = self.settings.changed(&self.buffer.options).join("  ");
+            self.show_message(&changed);
+
+            return Ok(());
+        }
+
+        // Options that change how the file is written change the buffer
+        let changes_file = args.iter().any(|arg| {
+            let arg = SetArg::parse(arg);
+
+            !matches!(arg, SetArg::Query(_))
+                && find(arg.name()).is_some_and(|def| FILE_OPTIONS.contains(&def.name))
+        });
+
+        if changes_file {
+            self.check_modifiable()?;
+        }
+
+        let file_options: Vec<Value> = FILE_OPTIONS.iter().map(|name| self.option(name)).collect();
+        let mut shown = Vec::new();
+
+        for arg in args {
+            match self
+                .settings
+                .apply(&mut self.buffer.options, arg, local_only)
+            {
+                Ok(Some(msg)) => shown.push(msg),
+                Ok(None) => {}
+                Err(err) => return Err(Error::InvalidOption(err)),
+            }
+        }
+
+        if !shown.is_empty() {
+            self.show_message(&shown.join("  "));
+        }
+
+        if FILE_OPTIONS
+            .iter()
+            .zip(file_options)
+            .any(|(name, value)| self.option(name) != value)
+        {
+            self.modified = true;
+        }
+
+        // Options like tabstop change the way the buffer is drawn
+        self.set_top_line(self.top_line());
 
-        stdout().into_raw_mode().unwrap().flush().unwrap();
+        Ok(())
     }
 }
@@ -0,0 +1,477 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::encoding;
+use crate::settings::{Options, Value};
+
+/// A section of an `.editorconfig` file, i.e. a glob with the properties that apply to the files
+/// it matches.
+struct Section {
+    glob: String,
+    properties: Vec<(String, String)>,
+}
+
+/// A parsed `.editorconfig` file.
+struct Config {
+    root: bool,
+    sections: Vec<Section>,
+}
+
+impl Config {
+    fn parse(text: &str) -> Self {
+        let mut config = Self {
+            root: false,
+            sections: Vec::new(),
+        };
+
+        for line in text.lines() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                config.sections.push(Section {
+                    glob: String::from(&line[1..line.len() - 1]),
+                    properties: Vec::new(),
+                });
+            } else if let Some(pos) = line.find('=') {
+                let key = line[..pos].trim().to_lowercase();
+                let value = line[pos + 1..].trim().to_lowercase();
+
+                match config.sections.last_mut() {
+                    Some(section) => section.properties.push((key, value)),
+                    None if key == "root" => config.root = value == "true",
+                    None => {}
+                }
+            }
+        }
+
+        config
+    }
+}
+
+/// A single part of a glob pattern.
+#[derive(Clone, Debug)]
+enum Token {
+    Char(char),
+    /// `*`, anything but a path separator
+    Any,
+    /// `**`, anything including path separators
+    AnyPath,
+    /// `?`, a single character that is not a path separator
+    One,
+    /// `[abc]`, `[a-z]` or `[!abc]`
+    Class(Vec<(char, char)>, bool),
+    /// `{1..10}`
+    Range(i64, i64),
+    /// `{a,b,c}`
+    Alt(Vec<Vec<Token>>),
+}
+
+fn parse_glob(pattern: &[char]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < pattern.len() {
+        match pattern[i] {
+            '*' if pattern.get(i + 1) == Some(&'*') => {
+                tokens.push(Token::AnyPath);
+                i += 1;
+            }
+            '*' => tokens.push(Token::Any),
+            '?' => tokens.push(Token::One),
+            '\\' if i + 1 < pattern.len() => {
+                tokens.push(Token::Char(pattern[i + 1]));
+                i += 1;
+            }
+            '[' => match pattern[i..].iter().position(|&c| c == ']') {
+                Some(len) if len > 1 => {
+                    let class = &pattern[i + 1..i + len];
+                    let negated = class[0] == '!';
+                    let class = if negated { &class[1..] } else { class };
+
+                    let mut ranges = Vec::new();
+                    let mut j = 0;
+                    while j < class.len() {
+                        if j + 2 < class.len() && class[j + 1] == '-' {
+                            ranges.push((class[j], class[j + 2]));
+                            j += 3;
+                        } else {
+                            ranges.push((class[j], class[j]));
+                            j += 1;
+                        }
+                    }
+
+                    tokens.push(Token::Class(ranges, negated));
+                    i += len;
+                }
+                _ => tokens.push(Token::Char('[')),
+            },
+            '{' => match closing_brace(&pattern[i..]) {
+                Some(len) => {
+                    let inner = &pattern[i + 1..i + len];
+                    tokens.push(parse_braces(inner));
+                    i += len;
+                }
+                None => tokens.push(Token::Char('{')),
+            },
+            c => tokens.push(Token::Char(c)),
+        }
+
+        i += 1;
+    }
+
+    tokens
+}
+
+/// Finds the position of the brace closing the one at the start of the pattern.
+fn closing_brace(pattern: &[char]) -> Option<usize> {
+    let mut depth = 0;
+
+    for (i, &c) in pattern.iter().enumerate() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Parses the inside of braces, which is either a numeric range or a list of alternatives.
+fn parse_braces(inner: &[char]) -> Token {
+    let text: String = inner.iter().collect();
+
+    if let Some(pos) = text.find("..") {
+        if let (Ok(from), Ok(to)) = (text[..pos].parse(), text[pos + 2..].parse()) {
+            return Token::Range(from, to);
+        }
+    }
+
+    // Split at top-level commas only
+    let mut alternatives = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, &c) in inner.iter().enumerate() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                alternatives.push(parse_glob(&inner[start..i]));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    if alternatives.is_empty() {
+        // A single word in braces is matched literally
+        let mut tokens = vec![Token::Char('{')];
+        tokens.extend(parse_glob(inner));
+        tokens.push(Token::Char('}'));
+
+        return Token::Alt(vec![tokens]);
+    }
+
+    alternatives.push(parse_glob(&inner[start..]));
+
+    Token::Alt(alternatives)
+}
+
+fn match_tokens(tokens: &[Token], text: &[char]) -> bool {
+    let (token, rest) = match tokens.split_first() {
+        Some(split) => split,
+        None => return text.is_empty(),
+    };
+
+    match token {
+        Token::Char(c) => text.first() == Some(c) && match_tokens(rest, &text[1..]),
+        Token::One => {
+            matches!(text.first(), Some(&c) if c != '/') && match_tokens(rest, &text[1..])
+        }
+        Token::Any => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| match_tokens(rest, &text[i..])),
+        Token::AnyPath => (0..=text.len()).any(|i| match_tokens(rest, &text[i..])),
+        Token::Class(ranges, negated) => match text.first() {
+            Some(&c) if c != '/' => {
+                let in_class = ranges.iter().any(|&(from, to)| from <= c && c <= to);
+
+                in_class != *negated && match_tokens(rest, &text[1..])
+            }
+            _ => false,
+        },
+        Token::Range(from, to) => {
+            let sign = if text.first() == Some(&'-') { 1 } else { 0 };
+            let digits = text[sign..]
+                .iter()
+                .take_while(|c| c.is_ascii_digit())
+                .count();
+
+            (sign + 1..=sign + digits).any(|len| {
+                let number: String = text[..len].iter().collect();
+
+                match number.parse::<i64>() {
+                    Ok(n) => *from <= n && n <= *to && match_tokens(rest, &text[len..]),
+                    Err(_) => false,
+                }
+            })
+        }
+        Token::Alt(alternatives) => alternatives.iter().any(|alternative| {
+            let mut tokens = alternative.clone();
+            tokens.extend_from_slice(rest);
+
+            match_tokens(&tokens, text)
+        }),
+    }
+}
+
+/// Checks whether a path (relative to the directory of the `.editorconfig` file, using `/` as
+/// separator) matches a section glob.
+fn glob_matches(glob: &str, path: &str) -> bool {
+    // Globs without a slash match files in any subdirectory
+    let glob = if glob.contains('/') {
+        format!("/{}", glob.trim_start_matches('/'))
+    } else {
+        format!("**/{}", glob)
+    };
+
+    let tokens = parse_glob(&glob.chars().collect::<Vec<char>>());
+    let path: Vec<char> = format!("/{}", path).chars().collect();
+
+    match_tokens(&tokens, &path)
+}
+
+/// Collects the properties that apply to a file from all `.editorconfig` files in its directory
+/// and the directories above, up to the first one marked as root.
+pub fn properties_for(file_name: &str) -> HashMap<String, String> {
+    let path = match env::current_dir() {
+        Ok(dir) => normalize(&dir.join(file_name)),
+        Err(_) => normalize(Path::new(file_name)),
+    };
+
+    let mut configs = Vec::new();
+    let mut dir = path.parent();
+
+    while let Some(current) = dir {
+        if let Ok(text) = fs::read_to_string(current.join(".editorconfig")) {
+            let config = Config::parse(&text);
+            let root = config.root;
+
+            configs.push((current, config));
+
+            if root {
+                break;
+            }
+        }
+
+        dir = current.parent();
+    }
+
+    // Files closer to the edited file take precedence, so they are applied last
+    let mut properties = HashMap::new();
+
+    for (dir, config) in configs.into_iter().rev() {
+        let relative = match path.strip_prefix(dir) {
+            Ok(relative) => relative_string(relative),
+            Err(_) => continue,
+        };
+
+        for section in config.sections {
+            if glob_matches(&section.glob, &relative) {
+                properties.extend(section.properties);
+            }
+        }
+    }
+
+    properties
+}
+
+/// Removes `.` and `..` from a path without looking at the file system, so that walking up from
+/// `../other/file` doesn't visit the current directory.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                // There is nothing above the root
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => normalized.push(component),
+            },
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+fn relative_string(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Applies EditorConfig properties to the options of a buffer.
+pub fn apply(options: &mut Options, properties: &HashMap<String, String>) {
+    let number = |key: &str| properties.get(key).and_then(|value| value.parse().ok());
+
+    match properties.get("indent_style").map(String::as_str) {
+        Some("tab") => options.set_by_name("expandtab", Value::Bool(false)),
+        Some("space") => options.set_by_name("expandtab", Value::Bool(true)),
+        _ => {}
+    }
+
+    if let Some(width) = number("tab_width").or_else(|| number("indent_size")) {
+        options.set_by_name("tabstop", Value::Int(width));
+    }
+
+    match properties.get("indent_size").map(String::as_str) {
+        Some("tab") => options.set_by_name("shiftwidth", Value::Int(0)),
+        Some(_) => {
+            if let Some(width) = number("indent_size") {
+                options.set_by_name("shiftwidth", Value::Int(width));
+            }
+        }
+        None => {}
+    }
+
+    let fileformat = match properties.get("end_of_line").map(String::as_str) {
+        Some("lf") => Some("unix"),
+        Some("crlf") => Some("dos"),
+        Some("cr") => Some("mac"),
+        _ => None,
+    };
+
+    if let Some(fileformat) = fileformat {
+        options.set_by_name("fileformat", Value::Str(String::from(fileformat)));
+    }
+
+    if let Some(charset) = properties.get("charset") {
+        let (encoding, bom) = match charset.as_str() {
+            "utf-8-bom" => (Some("utf-8"), true),
+            "utf-16be" | "utf-16le" => (encoding::normalize(charset), true),
+            other => (encoding::normalize(other), false),
+        };
+
+        if let Some(encoding) = encoding {
+            options.set_by_name("fileencoding", Value::Str(String::from(encoding)));
+            options.set_by_name("bomb", Value::Bool(bom));
+        }
+    }
+
+    match properties
+        .get("trim_trailing_whitespace")
+        .map(String::as_str)
+    {
+        Some("true") => options.set_by_name("trimwhitespace", Value::Bool(true)),
+        Some("false") => options.set_by_name("trimwhitespace", Value::Bool(false)),
+        _ => {}
+    }
+
+    match properties.get("insert_final_newline").map(String::as_str) {
+        Some("true") => options.set_by_name("fixendofline", Value::Bool(true)),
+        // The file must not end with a line break, not even the one it was read with
+        Some("false") => {
+            options.set_by_name("fixendofline", Value::Bool(false));
+            options.set_by_name("endofline", Value::Bool(false));
+        }
+        _ => {}
+    }
+
+    match properties.get("max_line_length").map(String::as_str) {
+        Some("off") => options.set_by_name("textwidth", Value::Int(0)),
+        Some(_) => {
+            if let Some(width) = number("max_line_length") {
+                options.set_by_name("textwidth", Value::Int(width));
+            }
+        }
+        None => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_matches_test() {
+        assert!(glob_matches("*", "main.rs"));
+        assert!(glob_matches("*.rs", "src/main.rs"));
+        assert!(!glob_matches("*.rs", "src/main.py"));
+        assert!(glob_matches("*.{js,py}", "lib/script.py"));
+        assert!(glob_matches("/src/*.rs", "src/main.rs"));
+        assert!(!glob_matches("/src/*.rs", "src/bin/main.rs"));
+        assert!(glob_matches("/src/**.rs", "src/bin/main.rs"));
+        assert!(glob_matches("file?.txt", "file1.txt"));
+        assert!(glob_matches("file[0-9].txt", "file7.txt"));
+        assert!(!glob_matches("file[!0-9].txt", "file7.txt"));
+        assert!(glob_matches("test{1..12}.c", "test10.c"));
+        assert!(!glob_matches("test{1..12}.c", "test13.c"));
+        assert!(glob_matches("{Makefile,*.mk}", "Makefile"));
+    }
+
+    #[test]
+    fn normalize_test() {
+        assert_eq!(
+            PathBuf::from("/home/other/x.rs"),
+            normalize(Path::new("/home/user/../other/./x.rs"))
+        );
+        assert_eq!(PathBuf::from("/x.rs"), normalize(Path::new("/../x.rs")));
+        assert_eq!(PathBuf::from("../x.rs"), normalize(Path::new("../x.rs")));
+    }
+
+    #[test]
+    fn parse_test() {
+        let config = Config::parse(
+            "root = true\n\n[*]\nindent_style = space\n# Comment\n[*.py]\nindent_size = 4\nx: y\n",
+        );
+
+        assert!(config.root);
+        assert_eq!(2, config.sections.len());
+        assert_eq!("*.py", config.sections[1].glob);
+        assert_eq!(
+            vec![(String::from("indent_size"), String::from("4"))],
+            config.sections[1].properties
+        );
+    }
+
+    #[test]
+    fn apply_test() {
+        let mut properties = HashMap::new();
+        properties.insert(String::from("indent_style"), String::from("tab"));
+        properties.insert(String::from("indent_size"), String::from("8"));
+        properties.insert(String::from("end_of_line"), String::from("crlf"));
+        properties.insert(String::from("insert_final_newline"), String::from("false"));
+
+        let mut options = Options::new();
+        apply(&mut options, &properties);
+
+        assert_eq!(Some(&Value::Bool(false)), options.get("expandtab"));
+        assert_eq!(Some(&Value::Int(8)), options.get("tabstop"));
+        assert_eq!(Some(&Value::Int(8)), options.get("shiftwidth"));
+        assert_eq!(
+            Some(&Value::Str(String::from("dos"))),
+            options.get("fileformat")
+        );
+        assert_eq!(Some(&Value::Bool(false)), options.get("fixendofline"));
+        assert_eq!(Some(&Value::Bool(false)), options.get("endofline"));
+    }
+}
@@ -0,0 +1,298 @@
+use std::char;
+
+/// All encodings rydl can read and write.
+pub const ENCODINGS: &[&str] = &["utf-8", "latin1", "utf-16le", "utf-16be"];
+
+/// In lossless mode, bytes that are invalid in the encoding of a file are mapped to these private
+/// use characters, so that they can be written back unchanged. Files that contain such characters
+/// themselves can't be read losslessly, as they couldn't be told apart.
+const ESCAPE_BASE: u32 = 0x10_ff00;
+
+/// Maps alternative names of an encoding to the name rydl uses for it.
+pub fn normalize(name: &str) -> Option<&'static str> {
+    match name.to_lowercase().as_str() {
+        "utf-8" | "utf8" => Some("utf-8"),
+        "latin1" | "latin-1" | "iso-8859-1" | "iso8859-1" => Some("latin1"),
+        "utf-16le" | "utf16le" | "ucs-2le" => Some("utf-16le"),
+        "utf-16be" | "utf16be" | "ucs-2be" | "utf-16" => Some("utf-16be"),
+        _ => None,
+    }
+}
+
+/// Gets the byte order mark of an encoding.
+pub fn bom(encoding: &str) -> &'static [u8] {
+    match encoding {
+        "utf-8" => b"\xef\xbb\xbf",
+        "utf-16le" => b"\xff\xfe",
+        "utf-16be" => b"\xfe\xff",
+        _ => b"",
+    }
+}
+
+/// Guesses the encoding of a file. Returns the encoding and whether the file starts with a byte
+/// order mark.
+pub fn detect(bytes: &[u8]) -> (&'static str, bool) {
+    for &encoding in &["utf-8", "utf-16le", "utf-16be"] {
+        if bytes.starts_with(bom(encoding)) {
+            return (encoding, true);
+        }
+    }
+
+    // UTF-16 text mostly consists of ASCII characters, i.e. every other byte is zero. This is
+    // checked first as zero bytes are valid UTF-8 as well
+    if bytes.len().is_multiple_of(2) {
+        let zeros = |offset: usize| {
+            bytes
+                .iter()
+                .skip(offset)
+                .step_by(2)
+                .filter(|&&b| b == 0)
+                .count()
+        };
+        let (even, odd) = (zeros(0), zeros(1));
+        let half = bytes.len() / 2;
+
+        if odd * 2 > half && even * 10 < half {
+            return ("utf-16le", false);
+        } else if even * 2 > half && odd * 10 < half {
+            return ("utf-16be", false);
+        }
+    }
+
+    if std::str::from_utf8(bytes).is_ok() {
+        return ("utf-8", false);
+    }
+
+    // Text that is mostly valid UTF-8 with a few broken bytes is still UTF-8
+    let (valid, invalid) = count_utf8(bytes);
+    if valid > invalid {
+        return ("utf-8", false);
+    }
+
+    ("latin1", false)
+}
+
+/// Counts the multi-byte characters and the invalid bytes in UTF-8 text.
+fn count_utf8(mut bytes: &[u8]) -> (usize, usize) {
+    let mut valid = 0;
+    let mut invalid = 0;
+
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => {
+                return (
+                    valid + text.chars().filter(|c| !c.is_ascii()).count(),
+                    invalid,
+                )
+            }
+            Err(err) => {
+                let (good, rest) = bytes.split_at(err.valid_up_to());
+                let text = std::str::from_utf8(good).unwrap();
+                let len = err.error_len().unwrap_or(rest.len());
+
+                valid += text.chars().filter(|c| !c.is_ascii()).count();
+                invalid += len;
+                bytes = &rest[len..];
+            }
+        }
+    }
+}
+
+fn escape(byte: u8) -> char {
+    char::from_u32(ESCAPE_BASE + u32::from(byte)).unwrap()
+}
+
+fn unescape(c: char) -> Option<u8> {
+    let code = c as u32;
+
+    if (ESCAPE_BASE..=ESCAPE_BASE + 0xff).contains(&code) {
+        Some((code - ESCAPE_BASE) as u8)
+    } else {
+        None
+    }
+}
+
+/// Decodes the content of a file (without its byte order mark). In lossless mode invalid bytes
+/// are kept as escape characters, otherwise they are an error. Returns the text and the number of
+/// invalid bytes.
+pub fn decode(bytes: &[u8], encoding: &str, lossless: bool) -> Result<(String, usize), String> {
+    let mut text = String::new();
+    let mut invalid = 0;
+
+    let mut keep = |text: &mut String, bytes: &[u8]| {
+        if !lossless {
+            return Err(format!(
+                "Invalid {} in file, use ++enc to override",
+                encoding
+            ));
+        }
+
+        text.extend(bytes.iter().map(|&b| escape(b)));
+        invalid += bytes.len();
+
+        Ok(())
+    };
+
+    match encoding {
+        "utf-8" => {
+            let mut bytes = bytes;
+
+            loop {
+                match std::str::from_utf8(bytes) {
+                    Ok(valid) => {
+                        text.push_str(valid);
+                        break;
+                    }
+                    Err(err) => {
+                        let (good, rest) = bytes.split_at(err.valid_up_to());
+                        let len = err.error_len().unwrap_or(rest.len());
+
+                        text.push_str(std::str::from_utf8(good).unwrap());
+                        keep(&mut text, &rest[..len])?;
+                        bytes = &rest[len..];
+                    }
+                }
+            }
+        }
+        "latin1" => text.extend(bytes.iter().map(|&b| char::from(b))),
+        "utf-16le" | "utf-16be" => {
+            let chunks = bytes.chunks_exact(2);
+            let rest = chunks.remainder();
+            let units: Vec<(u16, &[u8])> = chunks
+                .map(|pair| {
+                    let unit = if encoding == "utf-16le" {
+                        u16::from_le_bytes([pair[0], pair[1]])
+                    } else {
+                        u16::from_be_bytes([pair[0], pair[1]])
+                    };
+
+                    (unit, pair)
+                })
+                .collect();
+
+            let mut i = 0;
+            for decoded in char::decode_utf16(units.iter().map(|&(unit, _)| unit)) {
+                match decoded {
+                    Ok(c) => {
+                        text.push(c);
+                        i += c.len_utf16();
+                    }
+                    Err(_) => {
+                        keep(&mut text, units[i].1)?;
+                        i += 1;
+                    }
+                }
+            }
+
+            if !rest.is_empty() {
+                keep(&mut text, rest)?;
+            }
+        }
+        _ => return Err(format!("Unknown encoding: {}", encoding)),
+    }
+
+    // Each invalid byte is one escape character, more of them were in the file already
+    if invalid > 0 && text.chars().filter(|&c| unescape(c).is_some()).count() > invalid {
+        return Err(format!(
+            "Invalid {} in file that can't be kept, use ++enc to override",
+            encoding
+        ));
+    }
+
+    Ok((text, invalid))
+}
+
+/// Encodes text to be written to a file (without a byte order mark). If the text was decoded with
+/// escaped bytes, these are written back unchanged.
+pub fn encode(text: &str, encoding: &str, escaped: bool) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+
+    for c in text.chars() {
+        if let Some(byte) = unescape(c).filter(|_| escaped) {
+            bytes.push(byte);
+            continue;
+        }
+
+        match encoding {
+            "utf-8" => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            "latin1" => {
+                if (c as u32) <= 0xff {
+                    bytes.push(c as u8);
+                } else {
+                    return Err(format!("Cannot convert '{}' to latin1", c));
+                }
+            }
+            "utf-16le" | "utf-16be" => {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    if encoding == "utf-16le" {
+                        bytes.extend_from_slice(&unit.to_le_bytes());
+                    } else {
+                        bytes.extend_from_slice(&unit.to_be_bytes());
+                    }
+                }
+            }
+            _ => return Err(format!("Unknown encoding: {}", encoding)),
+        }
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_test() {
+        assert_eq!(("utf-8", false), detect("Grüße".as_bytes()));
+        assert_eq!(("utf-8", true), detect(b"\xef\xbb\xbfabc"));
+        assert_eq!(("utf-16le", true), detect(b"\xff\xfea\x00"));
+        assert_eq!(("utf-16le", false), detect(b"a\x00b\x00c\x00\n\x00"));
+        assert_eq!(("utf-16be", false), detect(b"\x00a\x00b\x00c\x00\n"));
+        assert_eq!(("latin1", false), detect(b"Gr\xfc\xdfe"));
+        assert_eq!(("utf-8", false), detect(b"Gr\xc3\xbc\xc3\x9fe \xff"));
+    }
+
+    #[test]
+    fn round_trip_test() {
+        for &encoding in ENCODINGS {
+            let text = "Grüße\n";
+            let bytes = encode(text, encoding, false).unwrap();
+
+            assert_eq!(
+                (String::from(text), 0),
+                decode(&bytes, encoding, false).unwrap()
+            );
+        }
+
+        assert!(encode("€", "latin1", false).is_err());
+    }
+
+    #[test]
+    fn lossless_test() {
+        let bytes = b"Gr\xc3\xbc\xfe\xff";
+
+        assert!(decode(bytes, "utf-8", false).is_err());
+
+        let (text, invalid) = decode(bytes, "utf-8", true).unwrap();
+        assert_eq!(2, invalid);
+        assert_eq!(bytes.to_vec(), encode(&text, "utf-8", true).unwrap());
+
+        let bytes = b"a\x00\x00\xd8b";
+        let (text, invalid) = decode(bytes, "utf-16le", true).unwrap();
+        assert_eq!(3, invalid);
+        assert_eq!(bytes.to_vec(), encode(&text, "utf-16le", true).unwrap());
+
+        // Characters from the escape range are only bytes if they were escaped when decoding
+        let text = "\u{10ff41}";
+        let bytes = encode(text, "utf-8", false).unwrap();
+        assert_eq!(
+            (String::from(text), 0),
+            decode(&bytes, "utf-8", true).unwrap()
+        );
+
+        let mut bytes = bytes;
+        bytes.push(0xff);
+        assert!(decode(&bytes, "utf-8", true).is_err());
+    }
+}
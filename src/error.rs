@@ -0,0 +1,111 @@
+use std::fmt;
+
+use crate::io;
+
+/// Everything that can go wrong while editing, the messages are shown to the user in the message line.
+#[derive(Debug)]
+pub enum Error {
+    /// A line number that is not in the buffer
+    LineOutOfRange {
+        line: usize,
+        len: usize,
+    },
+    /// A file could not be read
+    Read(String, std::io::Error),
+    /// A file could not be written
+    Write(String, std::io::Error),
+    Io(std::io::Error),
+
+    NoFileName,
+    NotSaved,
+    ReadOnly,
+    /// The buffer can't be changed because it is read-only or not modifiable
+    ChangeReadOnly,
+    NotModifiable,
+    FileExists,
+    ChangedOnDisk,
+    LastFile,
+    FirstFile,
+    UnknownCommand(String),
+    InvalidArgument(String),
+    InvalidOption(String),
+    /// A register to put from is empty
+    EmptyRegister(char),
+    /// A mapping that keeps leading to other mappings
+    RecursiveMapping,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::LineOutOfRange { line, len } => {
+                write!(f, "Line {} is out of range (1 to {})", line, len)
+            }
+            Self::Read(file_name, err) => write!(
+                f,
+                "Could not open \"{}\": {}",
+                file_name,
+                io::error_message(err)
+            ),
+            Self::Write(file_name, err) => write!(
+                f,
+                "Could not write \"{}\": {}",
+                file_name,
+                io::error_message(err)
+            ),
+            Self::Io(err) => write!(f, "{}", io::error_message(err)),
+            Self::NoFileName => write!(f, "No file name"),
+            Self::NotSaved => write!(f, "No write since last change (add ! to override)"),
+            Self::ReadOnly => write!(f, "'readonly' option is set (add ! to override)"),
+            Self::ChangeReadOnly => write!(f, "Cannot make changes, 'readonly' option is set"),
+            Self::NotModifiable => write!(f, "Cannot make changes, 'modifiable' is off"),
+            Self::FileExists => write!(f, "File exists (add ! to override)"),
+            Self::ChangedOnDisk => write!(
+                f,
+                "WARNING: The file has been changed since reading it (add ! to override)"
+            ),
+            Self::LastFile => write!(f, "Cannot go beyond last file"),
+            Self::FirstFile => write!(f, "Cannot go before first file"),
+            Self::UnknownCommand(command) => write!(f, "Not an editor command: {}", command),
+            Self::InvalidArgument(msg) | Self::InvalidOption(msg) => write!(f, "{}", msg),
+            Self::EmptyRegister(name) => write!(f, "Nothing in register {}", name),
+            Self::RecursiveMapping => write!(f, "Recursive mapping"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Read(_, err) | Self::Write(_, err) | Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_test() {
+        assert_eq!(
+            "Line 5 is out of range (1 to 3)",
+            Error::LineOutOfRange { line: 5, len: 3 }.to_string()
+        );
+
+        let err = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        assert_eq!(
+            "Could not write \"file.txt\": permission denied",
+            Error::Write(String::from("file.txt"), err).to_string()
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use std::path::Path;
+
+/// Guesses the filetype of a file from its name, returns an empty string if it is unknown.
+pub fn detect(file_name: &str) -> &'static str {
+    let path = Path::new(file_name);
+
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some("Makefile") | Some("makefile") | Some("GNUmakefile") => return "make",
+        Some("Dockerfile") => return "dockerfile",
+        Some(".editorconfig") => return "editorconfig",
+        _ => {}
+    }
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("rs") => "rust",
+        Some("py") | Some("pyw") => "python",
+        Some("c") | Some("h") => "c",
+        Some("cpp") | Some("cc") | Some("cxx") | Some("hpp") | Some("hh") => "cpp",
+        Some("java") => "java",
+        Some("js") | Some("mjs") => "javascript",
+        Some("ts") => "typescript",
+        Some("go") => "go",
+        Some("json") => "json",
+        Some("toml") => "toml",
+        Some("yml") | Some("yaml") => "yaml",
+        Some("md") | Some("markdown") => "markdown",
+        Some("sh") | Some("bash") => "sh",
+        Some("html") | Some("htm") => "html",
+        Some("css") => "css",
+        Some("txt") => "text",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_test() {
+        assert_eq!("rust", detect("src/main.rs"));
+        assert_eq!("python", detect("/tmp/script.py"));
+        assert_eq!("make", detect("project/Makefile"));
+        assert_eq!("", detect("README"));
+    }
+}
@@ -1,20 +1,62 @@
-use std::io::{stdin, stdout, Write};
-use termion::event::Key;
-use termion::input::TermRead;
-use termion::raw::IntoRawMode;
-use unicode_segmentation::UnicodeSegmentation;
+use std::cmp;
+use std::mem;
+use std::path::Path;
 
-use crate::drawer::Drawer;
 use crate::editor::{Editor, Mode as EditorMode};
+use crate::encoding;
+use crate::error::{Error, Result};
+use crate::filetype;
+use crate::indent::Indent;
 use crate::io::IO;
+use crate::key::Key;
+use crate::keymap;
+use crate::register::Register;
+use crate::settings::{self, Value};
+use crate::swap::Swap;
+use crate::util;
+
+#[cfg(feature = "terminal")]
+use {
+    crate::cmdline::HISTORY_FILE,
+    crate::drawer::Drawer,
+    crate::input::{self, Event},
+    crate::keymap::MapMode,
+    crate::terminal,
+    std::io::{stdout, Write},
+    termion::raw::IntoRawMode,
+};
+
+/// The largest count a command takes.
+const MAX_COUNT: usize = 99_999;
+
+/// The full names of all Ex commands, used for completion.
+pub const COMMANDS: &[&str] = &[
+    "args", "buffer", "buffers", "cmap", "cnoremap", "cunmap", "edit", "files", "imap", "inoremap",
+    "iunmap", "ls", "map", "messages", "next", "Next", "nmap", "nnoremap", "noremap", "nunmap",
+    "previous", "quit", "saveas", "set", "setlocal", "unmap", "view", "vmap", "vnoremap", "vunmap",
+    "wall", "wq", "write", "xit",
+];
 
 pub trait Handler {
+    #[cfg(feature = "terminal")]
     fn handle(&mut self);
+    #[cfg(feature = "terminal")]
     fn handle_keys(&mut self);
-    fn handle_command(&mut self, cmd_parts: Vec<&str>);
+    fn handle_closed(&mut self);
+    fn handle_command_key(&mut self, key: Key) -> Result<()>;
+    fn handle_insert_key(&mut self, key: Key) -> Result<()>;
+    fn handle_operator(&mut self, operator: char, motion: Key, count: usize) -> Result<()>;
+    fn finish_change(&mut self) -> Result<()>;
+    fn replay_insert(&mut self, keys: &[Key], count: usize, opens_line: bool) -> Result<()>;
+    fn repeat_change(&mut self, count: usize) -> Result<()>;
+    fn handle_command(&mut self, cmd_parts: Vec<&str>) -> Result<()>;
+    fn handle_write(&mut self, file_name: Option<&str>, force: bool) -> Result<()>;
+    fn handle_save_as(&mut self, file_name: &str, force: bool) -> Result<()>;
+    fn show_written(&mut self, file_name: &str, is_new: bool);
 }
 
 impl Handler for Editor {
+    #[cfg(feature = "terminal")]
     fn handle(&mut self) {
         while self.running {
             self.draw();
@@ -22,168 +64,1116 @@ impl Handler for Editor {
 
             stdout().flush().unwrap();
         }
+
+        self.remove_swap();
+        input::set_focus_events(&mut stdout(), false);
+
+        // Losing the history is not worth bothering the user about
+        let _ = self.command_history.save(HISTORY_FILE);
     }
 
+    #[cfg(feature = "terminal")]
     fn handle_keys(&mut self) {
-        let stdin = stdin();
-        let mut stdout = stdout().into_raw_mode().unwrap();
+        let _stdout = stdout().into_raw_mode().unwrap();
+
+        loop {
+            let map_mode = match self.mode {
+                EditorMode::Command => MapMode::Normal,
+                EditorMode::Insert | EditorMode::Replace => MapMode::Insert,
+            };
+
+            // The character that `r` replaces with and the key after `Ctrl-r` or `Ctrl-v` in
+            // Insert mode are taken as they are typed
+            let event = if self.pending_operator == Some('r') || self.pending_insert.is_some() {
+                self.next_unmapped_event()
+            } else {
+                self.next_event(map_mode)
+            };
+
+            let c = match event {
+                Event::Key(key) => key,
+                Event::FocusGained => {
+                    self.check_disk_changes();
+                    self.draw();
+
+                    continue;
+                }
+                Event::Idle => {
+                    // Nothing is typed, a good time to catch up on things
+                    self.check_disk_changes();
+                    self.flush_swap();
+                    self.draw();
+
+                    continue;
+                }
+                Event::FocusLost => continue,
+                Event::Closed => {
+                    self.handle_closed();
+                    break;
+                }
+            };
+
+            // Suspending works in every mode, only after `Ctrl-v` the key is inserted as it is
+            if c == Key::Ctrl('z') && self.pending_insert.is_none() {
+                terminal::suspend();
+                self.redraw();
+
+                continue;
+            }
 
-        for c in stdin.keys() {
             if self.mode == EditorMode::Command {
-                match c.unwrap() {
-                    Key::Char('i') => {
-                        self.mode = EditorMode::Insert;
-                        self.draw();
-                    }
-                    Key::Char('h') => {
-                        self.move_cursor_left('.'); // TODO see below
-                    }
-                    Key::Char('j') => {
-                        self.move_cursor_down();
-                    }
-                    Key::Char('k') => {
-                        self.move_cursor_up();
-                    }
-                    Key::Char('l') => {
-                        self.move_cursor_right('.'); // TODO replace '.' with current char (under cursor)
-                    }
-                    Key::Char(':') => {
+                let result = match c {
+                    Key::Char(':') if self.pending_operator.is_none() => {
+                        self.count = 0;
+                        self.change.clear();
                         self.read_command();
 
                         if !self.running {
                             break;
                         }
+
+                        Ok(())
                     }
-                    _ => {}
+                    _ => self.handle_command_key(c),
+                };
+
+                if let Err(err) = result {
+                    self.show_error(&err.to_string());
                 }
-            } else if self.mode == EditorMode::Insert {
-                match c.unwrap() {
-                    Key::Char(c) => {
-                        if c == '\n' {
-                            // Get the part of the current line that is ri::iter::FromIterator;ght to the cursor and
-                            // has to go to the next line
-                            let to_next_line = self
-                                .buffer
-                                .get_mut(self.current_line)
-                                .unwrap()
-                                .split_off(self.current_char - 1);
-
-                            self.buffer.insert(self.current_line + 1, to_next_line);
-
-                            self.current_line += 1;
-                            self.move_cursor_new_line();
-                        } else {
-                            if self.current_char
-                                == self.buffer.get(self.current_line).unwrap().len() + 1
-                            {
-                                // At the end of the line, the character can simply be appended
-                                self.buffer.get_mut(self.current_line).unwrap().push(c);
-                            } else {
-                                // Find the correct place to insert the character. Seperated by
-                                // unicode graphemes, rather than bytes.
-                                // ? This is incredibly inefficient. Is there a better way?
-                                let current_line_old =
-                                    self.buffer.get(self.current_line).unwrap().clone();
-                                let current_line = self.buffer.get_mut(self.current_line).unwrap();
-                                current_line.clear();
-                                for (i, cur) in current_line_old.graphemes(true).enumerate() {
-                                    current_line.push_str(cur);
-
-                                    if i == self.current_char - 2 {
-                                        current_line.push(c);
-                                    }
-                                }
-                            }
-
-                            self.move_cursor_right(c);
-                        }
 
-                        self.modified = true;
-                    }
-                    Key::Backspace => {
-                        if self.buffer.get(self.current_line).unwrap() == &String::new() {
-                            if self.current_line > 1 {
-                                self.buffer.remove(self.current_line);
-                                self.current_line -= 1;
-                                write!(stdout, "{}", termion::clear::CurrentLine).unwrap();
-                                self.move_cursor_eocl();
-                            }
-                        } else {
-                            // Find the correct character to be deleted. As with insertion,
-                            // graphemes must be used, and also like it, it is still
-                            // TODO: Incredibly inefficient
-                            let current_line_old =
-                                self.buffer.get(self.current_line).unwrap().clone();
-                            let current_line = self.buffer.get_mut(self.current_line).unwrap();
-                            for (i, (byte_pos, _)) in
-                                current_line_old.grapheme_indices(true).enumerate()
-                            {
-                                if i == self.current_char - 2 {
-                                    current_line.remove(byte_pos);
-                                }
-                            }
-
-                            self.move_cursor_left('.'); // TODO for current char
+                // The one command after `Ctrl-o` is done
+                if self.pending_operator.is_none() && self.count == 0 {
+                    if let Some(mode) = self.mode_after_command.take() {
+                        if self.mode == EditorMode::Command {
+                            self.mode = mode;
                         }
-
-                        self.modified = true;
                     }
-                    Key::Esc => {
-                        self.mode = EditorMode::Command;
+                }
+            } else if let Err(err) = self.handle_insert_key(c) {
+                self.show_error(&err.to_string());
+            }
+
+            self.update_swap();
+            self.draw();
+        }
+    }
+
+    /// Stops the editor because its input was closed, e.g. when the terminal went away. Unsaved
+    /// changes stay in the swap file, or in an emergency file if there is none.
+    fn handle_closed(&mut self) {
+        self.running = false;
+
+        if !self.modified {
+            return;
+        }
+
+        self.flush_swap();
+
+        if self.swap_file.is_none() {
+            let _ = self.write_emergency();
+        }
+
+        // The swap file is the only copy of the changes now, so it isn't removed when exiting
+        self.swap_file = None;
+    }
+
+    fn handle_command_key(&mut self, key: Key) -> Result<()> {
+        self.change.push(key);
+
+        if let Some(operator) = self.pending_operator.take() {
+            let count = cmp::max(self.count, 1);
+            self.count = 0;
+
+            let result = match (operator, key) {
+                ('r', Key::Char(c)) => self.replace_chars(c, count),
+                ('r', _) => Ok(()),
+                _ => self.handle_operator(operator, key, count),
+            };
+
+            return match result {
+                Ok(()) if operator != 'y' => self.finish_change(),
+                _ => {
+                    self.change.clear();
+                    result
+                }
+            };
+        }
+
+        // A count is typed before the command, `0` on its own goes to the start of the line
+        if let Key::Char(c) = key {
+            if let Some(digit) = c.to_digit(10).filter(|&d| d > 0 || self.count > 0) {
+                self.count = cmp::min(self.count * 10 + digit as usize, MAX_COUNT);
+                return Ok(());
+            }
+        }
+
+        let typed_count = mem::take(&mut self.count);
+        let count = cmp::max(typed_count, 1);
+        let is_change = matches!(
+            key,
+            Key::Char(
+                'a' | 'A'
+                    | 'i'
+                    | 'I'
+                    | 'o'
+                    | 'O'
+                    | 'R'
+                    | 'x'
+                    | 'X'
+                    | 'r'
+                    | 'J'
+                    | '~'
+                    | 'd'
+                    | 'p'
+                    | 'P'
+                    | '='
+            )
+        );
+
+        if is_change {
+            if let Err(err) = self.check_modifiable() {
+                self.change.clear();
+                return Err(err);
+            }
+        }
+
+        let result = match key {
+            Key::Char(operator @ ('d' | 'y' | '=' | 'r')) => {
+                // The count is for the operator
+                self.count = typed_count;
+                self.pending_operator = Some(operator);
+
+                return Ok(());
+            }
+            Key::Char('h') | Key::Left => {
+                (0..count).for_each(|_| self.move_cursor_left());
+                Ok(())
+            }
+            Key::Char('j') | Key::Down => {
+                (0..count).for_each(|_| self.move_cursor_down());
+                Ok(())
+            }
+            Key::Char('k') | Key::Up => {
+                (0..count).for_each(|_| self.move_cursor_up());
+                Ok(())
+            }
+            Key::Char('l') | Key::Right => {
+                (0..count).for_each(|_| self.move_cursor_right());
+                Ok(())
+            }
+            Key::Char('0') | Key::Home => {
+                self.set_cursor(self.current_line, 1);
+                Ok(())
+            }
+            Key::Char('^') => {
+                self.set_cursor(self.current_line, self.first_non_blank(self.current_line));
+                Ok(())
+            }
+            Key::Char('$') | Key::End => {
+                self.set_cursor(self.current_line + count - 1, usize::MAX);
+                Ok(())
+            }
+            Key::Char('i') | Key::Char('a') | Key::Char('A') | Key::Char('I') | Key::Char('R') => {
+                match key {
+                    Key::Char('a') => self.move_cursor_right(),
+                    Key::Char('A') => self.set_cursor(self.current_line, usize::MAX),
+                    Key::Char('I') => {
+                        self.set_cursor(self.current_line, self.first_non_blank(self.current_line))
                     }
                     _ => {}
                 }
+
+                self.mode = if key == Key::Char('R') {
+                    self.replaced.clear();
+                    EditorMode::Replace
+                } else {
+                    EditorMode::Insert
+                };
+                self.insert_start = Some(self.change.len());
+
+                Ok(())
             }
+            Key::Char('o') | Key::Char('O') => {
+                let result = self.open_line(key == Key::Char('o'));
 
-            self.draw();
+                self.mode = EditorMode::Insert;
+                self.insert_start = Some(self.change.len());
+
+                result
+            }
+            Key::Char('x') => {
+                let to = cmp::min(self.current_char + count, self.current_line_len() + 1);
+                self.delete_into_register(self.current_char, to)
+            }
+            Key::Char('X') => {
+                let from = cmp::max(self.current_char.saturating_sub(count), 1);
+                self.delete_into_register(from, self.current_char)
+            }
+            Key::Char('J') => self.join_lines(count),
+            Key::Char('~') => self.toggle_case(count),
+            Key::Char('p') => self.put(false, count),
+            Key::Char('P') => self.put(true, count),
+            Key::Char('.') => {
+                self.change.clear();
+                self.repeat_change(typed_count)
+            }
+            _ => Ok(()),
+        };
+
+        if result.is_err() || !is_change {
+            self.change.clear();
+            self.insert_start = None;
+        } else if self.insert_start.is_none() {
+            self.finish_change()?;
+        }
+
+        // Only in Insert mode, also when it is left for one command, the cursor can be behind
+        // the end of the line
+        if self.mode == EditorMode::Command && self.mode_after_command.is_none() {
+            self.clamp_cursor();
         }
+
+        result
     }
 
-    fn handle_command(&mut self, cmd_parts: Vec<&str>) {
-        match cmd_parts.len() {
-            1 => match cmd_parts[0] {
-                "q" => {
-                    if !self.modified {
-                        self.running = false;
-                    } else {
-                        self.show_error("No write since last change (add ! to override)");
+    fn handle_insert_key(&mut self, key: Key) -> Result<()> {
+        if key != Key::Esc {
+            self.check_modifiable()?;
+        }
+
+        if self.insert_start.is_some() {
+            self.change.push(key);
+        }
+
+        // `Backspace` only restores what was overwritten since the cursor last moved in another
+        // way, e.g. by `Enter` or the arrow keys
+        let overwrites = self.pending_insert.is_none()
+            && match key {
+                Key::Char(c) => c != '\n',
+                Key::Backspace | Key::Ctrl('h') => true,
+                _ => false,
+            };
+
+        if self.mode == EditorMode::Replace && !overwrites {
+            self.replaced.clear();
+        }
+
+        match self.pending_insert.take() {
+            // `Ctrl-r {reg}` inserts a register
+            Some('r') => {
+                if let Key::Char(name) = key {
+                    if let Some(text) = self.register_text(name) {
+                        self.insert_text(&text)?;
                     }
                 }
-                "q!" => {
-                    self.running = false;
+
+                return Ok(());
+            }
+            // `Ctrl-v` inserts the next key as it is, without indenting or expanding tabs
+            Some(_) => {
+                let c = match key {
+                    // A line break in the text would split the line without the buffer knowing
+                    Key::Char('\n') => Some('\r'),
+                    Key::Char(c) => Some(c),
+                    Key::Ctrl(c) if c.is_ascii() => Some(((c as u8) & 0x1f) as char),
+                    Key::Esc => Some('\x1b'),
+                    Key::Backspace => Some('\x7f'),
+                    _ => None,
+                };
+
+                if let Some(c) = c {
+                    self.insert_at_cursor(&c.to_string())?;
                 }
-                "e" => {
-                    if self.file_name != String::new() {
-                        self.load().expect("Could not load file to buffer");
-                    } else {
-                        self.show_error("No file name");
-                    }
+
+                return Ok(());
+            }
+            None => {}
+        }
+
+        let page = self.text_height();
+
+        match key {
+            Key::Char(c) if self.mode == EditorMode::Replace && c != '\n' => {
+                self.replace_at_cursor(c)?
+            }
+            Key::Backspace | Key::Ctrl('h') if self.mode == EditorMode::Replace => {
+                self.restore_replaced()?
+            }
+            Key::Char('\n') => {
+                self.split_line()?;
+                self.auto_indent()?;
+            }
+            Key::Char('\t') => self.insert_tab()?,
+            Key::Char(c) => {
+                self.insert_at_cursor(&c.to_string())?;
+                self.electric_indent(c)?;
+            }
+            Key::Ctrl('t') => self.shift_line(self.current_line, 1)?,
+            Key::Ctrl('d') => self.shift_line(self.current_line, -1)?,
+            // At the start of a line these delete the line break before it
+            Key::Backspace | Key::Ctrl('h') | Key::Ctrl('w') | Key::Ctrl('u')
+                if self.current_char == 1 =>
+            {
+                self.join_with_previous()?
+            }
+            Key::Backspace | Key::Ctrl('h') => self.delete_before_cursor()?,
+            Key::Ctrl('w') => {
+                let line = self.buffer.line(self.current_line)?;
+                let start = util::word_start(line, self.current_char - 1);
+
+                self.delete_range(start + 1, self.current_char)?;
+            }
+            Key::Ctrl('u') => self.delete_range(1, self.current_char)?,
+            Key::Delete => self.delete_at_cursor()?,
+            Key::Left => self.move_cursor_left(),
+            Key::Right => self.move_cursor_right(),
+            Key::Up => self.move_cursor_up(),
+            Key::Down => self.move_cursor_down(),
+            Key::Home => self.set_cursor(self.current_line, 1),
+            Key::End => self.set_cursor(self.current_line, usize::MAX),
+            Key::PageUp => {
+                self.set_top_line(self.top_line().saturating_sub(page).max(1));
+                self.set_cursor(self.current_line.saturating_sub(page), self.current_char);
+            }
+            Key::PageDown => {
+                let last_top = self.buffer.len().saturating_sub(page) + 1;
+
+                self.set_top_line(cmp::min(self.top_line() + page, last_top));
+                self.set_cursor(self.current_line + page, self.current_char);
+            }
+            Key::Ctrl('r') => self.pending_insert = Some('r'),
+            Key::Ctrl('v') => self.pending_insert = Some('v'),
+            // `Ctrl-o` runs one Command-mode command and comes back
+            Key::Ctrl('o') => {
+                if self.insert_start.is_some() {
+                    self.finish_change()?;
+                }
+
+                self.mode_after_command = Some(self.mode);
+                self.mode = EditorMode::Command;
+            }
+            Key::Esc => {
+                if self.insert_start.is_some() {
+                    self.finish_change()?;
                 }
-                "w" => {
-                    if self.file_name != String::new() {
-                        self.save().expect("Could not save buffer to file");
-                    } else {
-                        self.show_error("No file name");
+
+                self.mode = EditorMode::Command;
+                self.move_cursor_left();
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn handle_operator(&mut self, operator: char, motion: Key, count: usize) -> Result<()> {
+        // Find out the range of lines the operator applies to
+        let (from, to) = match motion {
+            Key::Char(c) if c == operator => (
+                self.current_line,
+                cmp::min(self.current_line + count - 1, self.buffer.len()),
+            ),
+            Key::Char('j') => (
+                self.current_line,
+                cmp::min(self.current_line + count, self.buffer.len()),
+            ),
+            Key::Char('k') => (
+                cmp::max(self.current_line.saturating_sub(count), 1),
+                self.current_line,
+            ),
+            Key::Char('G') => (self.current_line, self.buffer.len()),
+            _ => return Ok(()),
+        };
+
+        match operator {
+            '=' => {
+                self.check_modifiable()?;
+                self.reindent(from, to)?;
+            }
+            'd' => {
+                self.check_modifiable()?;
+
+                let lines = self.delete_lines(from, to)?;
+                self.registers.delete(Register::new(lines, true));
+            }
+            'y' => {
+                let lines = (from..=to)
+                    .map(|line_number| self.buffer.line(line_number).cloned())
+                    .collect::<Result<_>>()?;
+
+                self.registers.yank(Register::new(lines, true));
+                self.set_cursor(from, self.current_char);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Ends the change being typed, so that `.` repeats it. With a count, the text typed in
+    /// Insert mode is inserted that many times, e.g. `3ix<Esc>` inserts `xxx`.
+    fn finish_change(&mut self) -> Result<()> {
+        let mut keys = mem::take(&mut self.change);
+
+        if let Some(start) = self.insert_start.take() {
+            // Repeating always ends Insert mode with `Esc`, also when it was left with `Ctrl-o`
+            keys.pop();
+
+            let (count, _) = split_count(&keys);
+            let opens_line = matches!(keys[start - 1], Key::Char('o') | Key::Char('O'));
+
+            // Plain text is inserted all at once, typing it again is slow for large counts
+            let text: Option<String> = keys[start..]
+                .iter()
+                .map(|key| match key {
+                    Key::Char(c) if *c != '\n' && *c != '\t' => Some(*c),
+                    _ => None,
+                })
+                .collect();
+
+            match text {
+                Some(text) if !opens_line && self.mode == EditorMode::Insert && count > 1 => {
+                    self.insert_at_cursor(&text.repeat(count - 1))?;
+                }
+                _ => self.replay_insert(&keys[start..], count, opens_line)?,
+            }
+
+            keys.push(Key::Esc);
+        }
+
+        self.last_change = keys;
+
+        Ok(())
+    }
+
+    /// Types the keys of an insert again, so that it is done `count` times in all.
+    fn replay_insert(&mut self, keys: &[Key], count: usize, opens_line: bool) -> Result<()> {
+        for _ in 1..count {
+            if opens_line {
+                self.open_line(true)?;
+            }
+
+            for &key in keys {
+                self.handle_insert_key(key)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Repeats the last change (`.`), with the count given instead of its own one if there is one.
+    fn repeat_change(&mut self, count: usize) -> Result<()> {
+        let (_, digits) = split_count(&self.last_change);
+        let mut keys: Vec<Key> = if count > 0 {
+            count.to_string().chars().map(Key::Char).collect()
+        } else {
+            self.last_change[..digits].to_vec()
+        };
+
+        keys.extend_from_slice(&self.last_change[digits..]);
+
+        for key in keys {
+            match self.mode {
+                EditorMode::Command => self.handle_command_key(key)?,
+                EditorMode::Insert | EditorMode::Replace => self.handle_insert_key(key)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_command(&mut self, cmd_parts: Vec<&str>) -> Result<()> {
+        let local_only = match cmd_parts.first() {
+            Some(&"set") | Some(&"se") => Some(false),
+            Some(&"setlocal") | Some(&"setl") => Some(true),
+            _ => None,
+        };
+
+        if let Some((command, modes)) = cmd_parts
+            .first()
+            .and_then(|name| keymap::parse_command(name))
+        {
+            return self.map_keys(command, modes, &cmd_parts[1..]);
+        }
+
+        if let Some(local_only) = local_only {
+            let args = settings::join_escaped(&cmd_parts[1..]);
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+            return self.set_options(&args, local_only);
+        }
+
+        // Take out arguments like `++enc=latin1` that change how a file is read
+        let mut encoding = None;
+        let mut parts = Vec::new();
+
+        for part in cmd_parts {
+            match part
+                .strip_prefix("++enc=")
+                .or_else(|| part.strip_prefix("++encoding="))
+            {
+                Some(name) => match encoding::normalize(name) {
+                    Some(name) => encoding = Some(name),
+                    None => {
+                        return Err(Error::InvalidArgument(format!(
+                            "Unknown encoding: {}",
+                            name
+                        )))
                     }
+                },
+                None => parts.push(part),
+            }
+        }
+
+        let (command, args) = match parts.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return Ok(()),
+        };
+        let force = command.ends_with('!');
+        let file_name = match args {
+            [] => None,
+            [file_name] => Some(*file_name),
+            _ => {
+                return Err(Error::InvalidArgument(String::from(
+                    "Only one file name allowed",
+                )))
+            }
+        };
+
+        match command.trim_end_matches('!') {
+            "q" | "quit" => {
+                if self.modified && !force {
+                    return Err(Error::NotSaved);
+                }
+
+                self.running = false;
+            }
+            "e" | "edit" => match file_name {
+                _ if self.modified && !force => return Err(Error::NotSaved),
+                Some(file_name) => self.open(file_name, encoding)?,
+                None if self.file_name.is_empty() => return Err(Error::NoFileName),
+                // Reloading with `!` keeps the cursor where it was
+                None if force => self.reload(encoding)?,
+                None => self.open(&self.file_name.clone(), encoding)?,
+            },
+            "vie" | "view" => {
+                match file_name {
+                    _ if self.modified && !force => return Err(Error::NotSaved),
+                    Some(file_name) => self.open(file_name, encoding)?,
+                    None => {}
                 }
-                _ => {}
+
+                self.buffer
+                    .options
+                    .set_by_name("readonly", Value::Bool(true));
+            }
+            "w" | "write" => self.handle_write(file_name, force)?,
+            "sav" | "saveas" => match file_name {
+                Some(file_name) => self.handle_save_as(file_name, force)?,
+                None => return Err(Error::InvalidArgument(String::from("Argument required"))),
             },
-            2 => match cmd_parts[0] {
-                "e" => {
-                    // TODO add file existence check
-                    self.file_name = String::from(cmd_parts[1]);
+            "wq" => {
+                self.handle_write(file_name, force)?;
+                self.running = false;
+            }
+            // Like `:wq`, but only writes if there are changes
+            "x" | "xit" => {
+                if self.modified {
+                    self.handle_write(file_name, force)?;
+                }
 
-                    self.load().expect("Could not load file to buffer");
+                self.running = false;
+            }
+            "wa" | "wall" => {
+                if self.modified {
+                    self.handle_write(None, force)?;
+                }
+            }
+            "n" | "next" => {
+                if self.modified && !force {
+                    return Err(Error::NotSaved);
                 }
-                "w" => {
-                    // TODO add file existence check
-                    self.file_name = String::from(cmd_parts[1]);
 
-                    self.save().expect("Could not save buffer to file");
+                // With a file name, that file replaces the argument list
+                if let Some(file_name) = file_name {
+                    self.arglist = vec![String::from(file_name)];
+                    self.arg_index = 0;
+                } else if self.arg_index + 1 < self.arglist.len() {
+                    self.arg_index += 1;
+                } else {
+                    return Err(Error::LastFile);
+                }
+
+                self.open(&self.arglist[self.arg_index].clone(), encoding)?;
+            }
+            "N" | "Next" | "prev" | "previous" => {
+                if self.modified && !force {
+                    return Err(Error::NotSaved);
+                }
+
+                if self.arg_index == 0 || self.arglist.is_empty() {
+                    return Err(Error::FirstFile);
+                }
+
+                self.arg_index -= 1;
+                self.open(&self.arglist[self.arg_index].clone(), encoding)?;
+            }
+            // Switches to a file of the argument list, given by its number or its name
+            "b" | "buffer" => {
+                if self.modified && !force {
+                    return Err(Error::NotSaved);
+                }
+
+                let index = match file_name {
+                    Some(arg) => match arg.parse::<usize>() {
+                        Ok(number) => number.checked_sub(1),
+                        Err(_) => self.arglist.iter().position(|name| name == arg),
+                    },
+                    None => return Ok(()),
+                };
+
+                match index.filter(|&index| index < self.arglist.len()) {
+                    Some(index) => {
+                        self.arg_index = index;
+                        self.open(&self.arglist[index].clone(), encoding)?;
+                    }
+                    None => {
+                        return Err(Error::InvalidArgument(format!(
+                            "No matching buffer for {}",
+                            file_name.unwrap()
+                        )))
+                    }
+                }
+            }
+            "ar" | "args" => {
+                let list: Vec<String> = self
+                    .arglist
+                    .iter()
+                    .enumerate()
+                    .map(|(i, file_name)| {
+                        if i == self.arg_index {
+                            format!("[{}]", file_name)
+                        } else {
+                            file_name.clone()
+                        }
+                    })
+                    .collect();
+
+                self.show_message(&list.join(" "));
+            }
+            "mes" | "messages" => match file_name {
+                Some("clear") => self.messages.clear(),
+                Some(arg) => {
+                    return Err(Error::InvalidArgument(format!("Invalid argument: {}", arg)))
+                }
+                None => {
+                    let lines: Vec<String> = self
+                        .messages
+                        .iter()
+                        .map(|message| message.text.clone())
+                        .collect();
+
+                    self.show_lines(&lines);
                 }
-                _ => {}
             },
-            _ => {}
+            // Lists the argument list, together with the current file if it isn't part of it
+            "ls" | "buffers" | "files" => {
+                let mut names = self.arglist.clone();
+
+                if !names.contains(&self.file_name) {
+                    names.push(self.file_name.clone());
+                }
+
+                let lines: Vec<String> = names
+                    .iter()
+                    .enumerate()
+                    .map(|(i, file_name)| {
+                        let name = if file_name.is_empty() {
+                            "[No Name]"
+                        } else {
+                            file_name
+                        };
+
+                        if *file_name == self.file_name {
+                            let modified = if self.modified { "+" } else { " " };
+
+                            format!(
+                                "{:>3} %a {} \"{}\" line {}",
+                                i + 1,
+                                modified,
+                                name,
+                                self.current_line
+                            )
+                        } else {
+                            format!("{:>3}      \"{}\"", i + 1, name)
+                        }
+                    })
+                    .collect();
+
+                self.show_lines(&lines);
+            }
+            _ => return Err(Error::UnknownCommand(String::from(command))),
+        }
+
+        Ok(())
+    }
+
+    /// Writes the buffer to its file or, if a file name is given, to another file.
+    fn handle_write(&mut self, file_name: Option<&str>, force: bool) -> Result<()> {
+        let file_name = match file_name {
+            // Without a file name of its own, the buffer takes the one it is written to
+            Some(file_name) if self.file_name.is_empty() => {
+                return self.handle_save_as(file_name, force);
+            }
+            Some(file_name) if file_name != self.file_name => file_name,
+            _ if self.file_name.is_empty() => return Err(Error::NoFileName),
+            _ => {
+                if !force && self.option("readonly").as_bool().unwrap() {
+                    return Err(Error::ReadOnly);
+                }
+
+                if !force && self.changed_on_disk() {
+                    return Err(Error::ChangedOnDisk);
+                }
+
+                let is_new = self.buffer.is_new;
+
+                self.save()
+                    .map_err(|err| Error::Write(self.file_name.clone(), err))?;
+                self.remove_swap();
+                self.show_written(&self.file_name.clone(), is_new);
+
+                return Ok(());
+            }
+        };
+
+        // Writing a copy of the buffer to another file
+        let exists = Path::new(file_name).exists();
+
+        if exists && !force {
+            return Err(Error::FileExists);
+        }
+
+        self.write_to(file_name)
+            .map_err(|err| Error::Write(String::from(file_name), err))?;
+        self.show_written(file_name, !exists);
+
+        Ok(())
+    }
+
+    /// Writes the buffer to another file, which then becomes the file of the buffer.
+    fn handle_save_as(&mut self, file_name: &str, force: bool) -> Result<()> {
+        if file_name != self.file_name && Path::new(file_name).exists() && !force {
+            return Err(Error::FileExists);
+        }
+
+        let previous_name = mem::replace(&mut self.file_name, String::from(file_name));
+        let is_new = !Path::new(file_name).exists();
+
+        if let Err(err) = self.save() {
+            self.file_name = previous_name;
+
+            return Err(Error::Write(String::from(file_name), err));
+        }
+
+        // The swap file belongs to the old name
+        self.remove_swap();
+
+        if self.option("filetype").as_str() == Some("") {
+            let filetype = filetype::detect(&self.file_name);
+            self.buffer
+                .options
+                .set_by_name("filetype", Value::Str(String::from(filetype)));
+        }
+
+        self.show_written(file_name, is_new);
+
+        Ok(())
+    }
+
+    fn show_written(&mut self, file_name: &str, is_new: bool) {
+        self.show_message(&format!(
+            "\"{}\" {}{}L written",
+            file_name,
+            if is_new { "[New] " } else { "" },
+            self.buffer.len()
+        ));
+    }
+}
+
+/// Gets the count a change starts with (1 without one) and the number of keys it is typed with.
+fn split_count(keys: &[Key]) -> (usize, usize) {
+    let digits = keys
+        .iter()
+        .take_while(|key| matches!(key, Key::Char('0'..='9')))
+        .count();
+    let count = keys[..digits]
+        .iter()
+        .filter_map(|key| match key {
+            Key::Char(c) => c.to_digit(10),
+            _ => None,
+        })
+        .fold(0, |count, digit| count * 10 + digit as usize);
+
+    (cmp::max(count, 1), digits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::MapMode;
+    use crate::message::Severity;
+    use std::fs;
+
+    #[test]
+    fn insert_test() {
+        let mut editor = Editor::headless();
+
+        for c in "fn main() {\n}".chars() {
+            editor.handle_insert_key(Key::Char(c)).unwrap();
         }
+
+        assert_eq!(
+            vec!["fn main() {", "}"],
+            editor.buffer.iter().collect::<Vec<_>>()
+        );
+
+        editor.handle_insert_key(Key::Backspace).unwrap();
+        editor.handle_insert_key(Key::Char('\n')).unwrap();
+        editor.handle_insert_key(Key::Char('x')).unwrap();
+
+        assert_eq!("", editor.buffer.get(2).unwrap());
+        assert_eq!("    x", editor.buffer.get(3).unwrap());
+        assert!(editor.modified);
+    }
+
+    #[test]
+    fn insert_keys_test() {
+        let mut editor = Editor::headless();
+        let lines = |editor: &Editor| editor.buffer.iter().cloned().collect::<Vec<_>>().join("|");
+
+        for c in "one two\nthree".chars() {
+            editor.handle_insert_key(Key::Char(c)).unwrap();
+        }
+
+        // Backspace at the start of a line joins it with the one before
+        editor.handle_insert_key(Key::Home).unwrap();
+        editor.handle_insert_key(Key::Backspace).unwrap();
+        assert_eq!("one twothree", lines(&editor));
+        assert_eq!((1, 8), (editor.current_line, editor.current_char));
+
+        editor.handle_insert_key(Key::Ctrl('w')).unwrap();
+        assert_eq!("one three", lines(&editor));
+
+        editor.handle_insert_key(Key::Ctrl('u')).unwrap();
+        assert_eq!("three", lines(&editor));
+
+        editor
+            .registers
+            .set('a', Register::new(vec![String::from("x\ny")], false));
+        editor.handle_insert_key(Key::Ctrl('r')).unwrap();
+        editor.handle_insert_key(Key::Char('a')).unwrap();
+        assert_eq!("x|ythree", lines(&editor));
+
+        // Delete at the end of a line joins the next one
+        editor.handle_insert_key(Key::Up).unwrap();
+        editor.handle_insert_key(Key::End).unwrap();
+        editor.handle_insert_key(Key::Delete).unwrap();
+        assert_eq!("xythree", lines(&editor));
+
+        editor.run_command("set et");
+        editor.handle_insert_key(Key::Ctrl('v')).unwrap();
+        editor.handle_insert_key(Key::Char('\t')).unwrap();
+        assert_eq!("x\tythree", lines(&editor));
+
+        editor.handle_insert_key(Key::Ctrl('v')).unwrap();
+        editor.handle_insert_key(Key::Char('\n')).unwrap();
+        assert_eq!("x\t\rythree", lines(&editor));
+
+        editor.handle_insert_key(Key::Ctrl('v')).unwrap();
+        editor.handle_insert_key(Key::Ctrl('ä')).unwrap();
+        assert_eq!("x\t\rythree", lines(&editor));
+    }
+
+    /// Types keys in whatever mode the editor is in, `\x1b` is `Esc` and `\x7f` is `Backspace`.
+    fn type_keys(editor: &mut Editor, keys: &str) {
+        for c in keys.chars() {
+            let key = match c {
+                '\x1b' => Key::Esc,
+                '\x7f' => Key::Backspace,
+                c => Key::Char(c),
+            };
+
+            match editor.mode {
+                EditorMode::Command => editor.handle_command_key(key),
+                _ => editor.handle_insert_key(key),
+            }
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn command_keys_test() {
+        let mut editor = Editor::headless();
+
+        type_keys(&mut editor, "ione two\nthree\x1bk0x2xP");
+        assert_eq!("ne two", editor.buffer.get(1).unwrap());
+        assert_eq!(2, editor.current_char);
+
+        type_keys(&mut editor, "$X0rN3~J");
+        assert_eq!("nE to three", editor.buffer.get(1).unwrap());
+        assert_eq!((1, 6), (editor.buffer.len(), editor.current_char));
+
+        // The cursor stays on the last character, also when it is deleted
+        type_keys(&mut editor, "$~x$x");
+        assert_eq!("nE to thr", editor.buffer.get(1).unwrap());
+        assert_eq!(9, editor.current_char);
+
+        // Counts repeat inserted text, `.` repeats the whole change
+        type_keys(&mut editor, "A!\x1b3ix\x1b.2.");
+        assert_eq!("nE to thrxxxxxxxx!", editor.buffer.get(1).unwrap());
+        assert_eq!(15, editor.current_char);
+
+        let mut large = Editor::headless();
+        type_keys(&mut large, "99999iab\x1b");
+        assert_eq!(199_998, large.current_line_len());
+
+        editor.run_command("set nomodifiable");
+        assert!(editor.handle_command_key(Key::Char('x')).is_err());
+        assert!(editor.change.is_empty());
+    }
+
+    #[test]
+    fn line_keys_test() {
+        let mut editor = Editor::headless();
+
+        type_keys(&mut editor, "ione\ntwo\nthree\x1bkkyyjp2ddP");
+        assert_eq!("one", editor.registers.get('0').unwrap().text());
+        assert_eq!("one\nthree", editor.registers.get('1').unwrap().text());
+
+        type_keys(&mut editor, "jjp2ofoo\x1b");
+        assert_eq!(8, editor.buffer.len());
+
+        // Backspace in Replace mode puts back what was overwritten
+        type_keys(&mut editor, "7k0Rabcd\x7f\x7f\x1bj0.");
+        assert_eq!(
+            vec!["abe", "abe", "three", "two", "one", "foo", "foo", "three"],
+            editor.buffer.iter().collect::<Vec<_>>()
+        );
+
+        // Nothing is restored after a line break or moving the cursor
+        type_keys(&mut editor, "0Rx\n\x7f");
+        for key in [Key::Home, Key::Backspace, Key::Esc] {
+            editor.handle_insert_key(key).unwrap();
+        }
+        assert_eq!(
+            vec!["abe", "x", "be", "three"],
+            editor.buffer.iter().take(4).collect::<Vec<_>>()
+        );
+
+        type_keys(&mut editor, "kk9dd");
+        assert_eq!(vec![""], editor.buffer.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn commands_test() {
+        // Every command that is completed must also be run
+        for command in COMMANDS {
+            let mut editor = Editor::headless();
+            let result = editor.handle_command(vec![command]);
+
+            assert!(
+                !matches!(result, Err(Error::UnknownCommand(_))),
+                "{} is not dispatched",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn closed_test() {
+        let dir = util::TempDir::new("closed");
+
+        let mut editor = Editor::headless();
+        editor.file_name = String::from(dir.join("file.txt").to_str().unwrap());
+        type_keys(&mut editor, "ichanged\x1b");
+        editor.update_swap();
+
+        editor.handle_closed();
+        editor.remove_swap();
+        assert!(!editor.running);
+
+        let swap = fs::read_to_string(&editor.swap_paths()[0]).unwrap();
+        assert!(swap.contains("changed"));
+    }
+
+    #[test]
+    fn write_deleted_test() {
+        let dir = util::TempDir::new("write-deleted");
+        let path = dir.join("file.txt");
+        fs::write(&path, "one\n").unwrap();
+
+        let mut editor = Editor::headless();
+        editor.open(path.to_str().unwrap(), None).unwrap();
+
+        // A file that is gone can be written again without `!`
+        fs::remove_file(&path).unwrap();
+        editor.handle_command(vec!["w"]).unwrap();
+        assert_eq!("one\n", fs::read_to_string(&path).unwrap());
+    }
+
+    #[test]
+    fn command_test() {
+        let dir = util::TempDir::new("command");
+        let path = dir.join("file.txt");
+        let file_name = path.to_str().unwrap();
+        fs::write(&path, "one\ntwo\n").unwrap();
+
+        let mut editor = Editor::headless();
+        editor.run_command(&format!("e {}", file_name));
+        assert_eq!(2, editor.buffer.len());
+
+        editor.run_command("set ff=dos");
+        assert!(editor.modified);
+
+        // Unsaved changes keep the editor from quitting
+        editor.run_command("q");
+        assert!(editor.running);
+        assert_eq!(1, editor.error_count);
+
+        let last = editor.messages.iter().last().unwrap();
+        assert_eq!(Severity::Error, last.severity);
+        assert_eq!(Error::NotSaved.to_string(), last.text);
+
+        editor.run_command("messages clear");
+        assert!(editor.messages.is_empty());
+
+        editor.run_command("x");
+        assert!(!editor.running);
+        assert_eq!("one\r\ntwo\r\n", fs::read_to_string(&path).unwrap());
+
+        let mut editor = Editor::headless();
+        editor.run_script(&format!("\" a comment\nview {}\nbogus\n", file_name));
+        assert_eq!(1, editor.error_count);
+        assert!(editor.check_modifiable().is_err());
+    }
+
+    #[test]
+    fn map_test() {
+        let mut editor = Editor::headless();
+        editor.run_command("set mapleader=,");
+        editor.run_command("nnoremap <leader>w <Cmd>write<CR>");
+        editor.run_command("map! jk <Esc>");
+
+        let mappings = editor.keymap.find(MapMode::Normal, &[Key::Char(',')]);
+        assert_eq!(1, mappings.len());
+        assert_eq!("n  ,w           * <Cmd>write<CR>", mappings[0].describe());
+        assert_eq!(1, editor.keymap.find(MapMode::CommandLine, &[]).len());
+
+        editor.run_command("iunmap jk");
+        assert!(editor.keymap.find(MapMode::Insert, &[]).is_empty());
+
+        editor.run_command("iunmap jk");
+        assert_eq!(1, editor.error_count);
     }
 }
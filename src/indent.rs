@@ -0,0 +1,345 @@
+use std::collections::HashMap;
+
+use crate::editor::Editor;
+use crate::error::Result;
+use crate::settings::{TabType, Value};
+use crate::util;
+
+/// Rules that decide how lines are indented, these differ between filetypes.
+pub trait IndentRules {
+    /// Whether the line following the given one should be indented one level deeper.
+    fn indents_after(&self, line: &str) -> bool;
+
+    /// Whether the given line should be indented one level less than the line before.
+    fn dedents(&self, line: &str) -> bool;
+
+    /// Whether the end of a block can be told from the lines. If not, the indentation itself
+    /// ends blocks, and only lines after an indenting line or dedenting lines can be re-indented.
+    fn infers_dedent(&self) -> bool;
+}
+
+/// Indents after opening and dedents on closing brackets, used for most languages.
+pub struct BracketRules;
+
+impl IndentRules for BracketRules {
+    fn indents_after(&self, line: &str) -> bool {
+        line.trim_end().ends_with(['{', '(', '['])
+    }
+
+    fn dedents(&self, line: &str) -> bool {
+        line.trim_start().starts_with(['}', ')', ']'])
+    }
+
+    fn infers_dedent(&self) -> bool {
+        true
+    }
+}
+
+/// Additionally indents after a trailing colon, used for Python and YAML.
+pub struct ColonRules;
+
+impl IndentRules for ColonRules {
+    fn indents_after(&self, line: &str) -> bool {
+        BracketRules.indents_after(line) || line.trim_end().ends_with(':')
+    }
+
+    fn dedents(&self, line: &str) -> bool {
+        BracketRules.dedents(line)
+    }
+
+    fn infers_dedent(&self) -> bool {
+        false
+    }
+}
+
+/// Gets the indent rules to use for the given filetype.
+pub fn rules_for(filetype: &str) -> Box<dyn IndentRules> {
+    match filetype {
+        "python" | "yaml" => Box::new(ColonRules),
+        _ => Box::new(BracketRules),
+    }
+}
+
+/// The indentation style of a file as detected by `detect_style`.
+#[derive(Debug, PartialEq)]
+pub struct IndentStyle {
+    pub tab_type: TabType,
+    /// The width of one indent level, only known for soft tabs
+    pub width: Option<usize>,
+    pub tab_lines: usize,
+    pub space_lines: usize,
+}
+
+impl IndentStyle {
+    pub fn is_mixed(&self) -> bool {
+        self.tab_lines > 0 && self.space_lines > 0
+    }
+}
+
+/// Guesses whether the given lines are indented using tabs or spaces, and how wide one indent
+/// level is. Returns `None` if there are no indented lines to guess from.
+pub fn detect_style<'a>(lines: impl Iterator<Item = &'a String>) -> Option<IndentStyle> {
+    let mut tab_lines = 0;
+    let mut space_lines = 0;
+    let mut widths: HashMap<usize, usize> = HashMap::new();
+    let mut previous_width = 0;
+
+    for line in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let indentation = util::indentation(line);
+        let rest = &line[indentation.len()..];
+
+        if indentation.starts_with('\t') {
+            tab_lines += 1;
+            previous_width = 0;
+        } else if !indentation.is_empty() {
+            // A single space often only aligns the stars of a block comment
+            if indentation.len() == 1 && rest.starts_with('*') {
+                continue;
+            }
+
+            space_lines += 1;
+
+            if indentation.len() > previous_width {
+                *widths
+                    .entry(indentation.len() - previous_width)
+                    .or_insert(0) += 1;
+            }
+
+            previous_width = indentation.len();
+        } else {
+            previous_width = 0;
+        }
+    }
+
+    if tab_lines == 0 && space_lines == 0 {
+        return None;
+    }
+
+    let (tab_type, width) = if tab_lines >= space_lines {
+        (TabType::Hard, None)
+    } else {
+        // The most common increase in indentation is taken as the width of one level, smaller
+        // widths win on a tie
+        let width = widths
+            .into_iter()
+            .filter(|(width, _)| (2..=8).contains(width))
+            .max_by_key(|&(width, count)| (count, std::cmp::Reverse(width)))
+            .map(|(width, _)| width);
+
+        (TabType::Soft, width)
+    };
+
+    Some(IndentStyle {
+        tab_type,
+        width,
+        tab_lines,
+        space_lines,
+    })
+}
+
+/// Finds the last non-blank line before the given line number.
+fn previous_non_blank(editor: &Editor, line_number: usize) -> Option<&String> {
+    (1..line_number)
+        .rev()
+        .filter_map(|i| editor.buffer.get(i))
+        .find(|line| !line.trim().is_empty())
+}
+
+pub trait Indent {
+    fn indent_rules(&self) -> Option<Box<dyn IndentRules>>;
+    fn compute_indent(&self, line_number: usize) -> usize;
+    fn auto_indent(&mut self) -> Result<()>;
+    fn electric_indent(&mut self, c: char) -> Result<()>;
+    fn reindent(&mut self, from: usize, to: usize) -> Result<()>;
+    fn detect_indent(&mut self) -> Option<String>;
+}
+
+impl Indent for Editor {
+    /// Gets the indent rules for the current buffer, if smart indenting is enabled.
+    fn indent_rules(&self) -> Option<Box<dyn IndentRules>> {
+        if self.option("smartindent").as_bool().unwrap() {
+            Some(rules_for(self.option("filetype").as_str().unwrap()))
+        } else {
+            None
+        }
+    }
+
+    /// Computes the width a line should be indented by, based on the previous non-blank line.
+    fn compute_indent(&self, line_number: usize) -> usize {
+        let previous = match previous_non_blank(self, line_number) {
+            Some(previous) => previous,
+            None => return 0,
+        };
+
+        let mut width = util::indent_width(previous, self.tab_width());
+
+        if let Some(rules) = self.indent_rules() {
+            if rules.indents_after(previous) {
+                width += self.shift_width();
+            }
+
+            if self
+                .buffer
+                .get(line_number)
+                .is_some_and(|line| rules.dedents(line))
+            {
+                width = width.saturating_sub(self.shift_width());
+            }
+        }
+
+        width
+    }
+
+    /// Indents the current line after it was created by splitting a line.
+    fn auto_indent(&mut self) -> Result<()> {
+        if !self.option("autoindent").as_bool().unwrap() {
+            return Ok(());
+        }
+
+        let width = self.compute_indent(self.current_line);
+        self.set_indent(self.current_line, width)
+    }
+
+    /// Dedents the current line when a closing bracket was typed at its start.
+    fn electric_indent(&mut self, c: char) -> Result<()> {
+        let rules = match self.indent_rules() {
+            Some(rules) => rules,
+            None => return Ok(()),
+        };
+
+        let line = self.buffer.line(self.current_line)?;
+
+        if line.trim() == c.to_string() && rules.dedents(line) {
+            let width = self.compute_indent(self.current_line);
+            self.set_indent(self.current_line, width)?;
+        }
+
+        Ok(())
+    }
+
+    /// Re-indents all lines in the given range. Lines whose indentation can't be told, like the
+    /// first line after a block in Python, are left alone.
+    fn reindent(&mut self, from: usize, to: usize) -> Result<()> {
+        let rules = self.indent_rules();
+
+        for line_number in from..=to {
+            let line = self.buffer.line(line_number)?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            if let Some(rules) = rules.as_ref().filter(|rules| !rules.infers_dedent()) {
+                let indents = previous_non_blank(self, line_number)
+                    .is_some_and(|previous| rules.indents_after(previous));
+
+                if !indents && !rules.dedents(line) {
+                    continue;
+                }
+            }
+
+            let width = self.compute_indent(line_number);
+            self.set_indent(line_number, width)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the buffer-local tab options to the indentation style of the buffer. Returns a
+    /// notice if the buffer mixes tabs and spaces.
+    fn detect_indent(&mut self) -> Option<String> {
+        if !self.option("detectindent").as_bool().unwrap() {
+            return None;
+        }
+
+        let style = detect_style(self.buffer.iter())?;
+        let options = &mut self.buffer.options;
+
+        options.set_by_name("expandtab", Value::Bool(style.tab_type == TabType::Soft));
+
+        if let Some(width) = style.width {
+            options.set_by_name("tabstop", Value::Int(width));
+            options.set_by_name("shiftwidth", Value::Int(0));
+        }
+
+        if style.is_mixed() {
+            Some(format!(
+                "Mixed indentation ({} lines with tabs, {} with spaces), using {}",
+                style.tab_lines,
+                style.space_lines,
+                if style.tab_type == TabType::Hard {
+                    "tabs"
+                } else {
+                    "spaces"
+                }
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bracket_rules_test() {
+        assert!(BracketRules.indents_after("fn main() {"));
+        assert!(BracketRules.indents_after("let v = vec!["));
+        assert!(!BracketRules.indents_after("if x:"));
+        assert!(BracketRules.dedents("    }"));
+        assert!(!BracketRules.dedents("    x;"));
+    }
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn detect_style_test() {
+        let spaces = lines("fn main() {\n  if x {\n    y();\n  }\n}");
+        let style = detect_style(spaces.iter()).unwrap();
+        assert_eq!(TabType::Soft, style.tab_type);
+        assert_eq!(Some(2), style.width);
+        assert!(!style.is_mixed());
+
+        let tabs = lines("fn main() {\n\tif x {\n\t\ty();\n    z();\n\t}\n}");
+        let style = detect_style(tabs.iter()).unwrap();
+        assert_eq!(TabType::Hard, style.tab_type);
+        assert!(style.is_mixed());
+
+        let comment = lines("/*\n * Comment\n */\nfn main() {}");
+        assert_eq!(None, detect_style(comment.iter()));
+    }
+
+    #[test]
+    fn colon_rules_test() {
+        assert!(ColonRules.indents_after("def main():"));
+        assert!(ColonRules.indents_after("foo = {"));
+        assert!(ColonRules.dedents("]"));
+    }
+
+    #[test]
+    fn reindent_test() {
+        let mut editor = Editor::headless();
+        editor.run_command("setlocal filetype=python shiftwidth=4");
+
+        for line in lines("if x:\ny()\n      z()\nw()\nv = [\n1,\n]") {
+            editor.buffer.push(line);
+        }
+
+        editor.buffer.remove(1).unwrap();
+        editor.reindent(1, editor.buffer.len()).unwrap();
+
+        // The statement after the block stays where it is, it could belong to either
+        assert_eq!(
+            lines("if x:\n    y()\n      z()\nw()\nv = [\n    1,\n]"),
+            editor.buffer.iter().cloned().collect::<Vec<_>>()
+        );
+    }
+}
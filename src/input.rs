@@ -0,0 +1,267 @@
+use std::collections::VecDeque;
+use std::io::{Error, Write};
+use std::time::Duration;
+use termion::event;
+
+use crate::key::Key;
+
+/// Makes the terminal report when it gains or loses focus.
+pub const ENABLE_FOCUS_EVENTS: &str = "\x1b[?1004h";
+pub const DISABLE_FOCUS_EVENTS: &str = "\x1b[?1004l";
+
+/// How long to wait for the rest of a key sequence, like Vim's `ttimeoutlen`.
+const SEQUENCE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// An input event.
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Key(Key),
+    FocusGained,
+    FocusLost,
+    /// No input arrived within the timeout
+    Idle,
+    /// The input was closed
+    Closed,
+}
+
+/// Reads input events from the terminal. Unlike reading from `std::io::stdin`, this allows to
+/// wait for input with a timeout, as no input is hidden away in a buffer.
+pub struct Input {
+    queue: VecDeque<u8>,
+    fd: i32,
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            fd: libc::STDIN_FILENO,
+        }
+    }
+
+    /// Waits for input to become available. Returns false if the timeout ran out first.
+    fn poll(&self, timeout: Option<Duration>) -> bool {
+        let mut fds = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.map_or(-1, |timeout| timeout.as_millis() as libc::c_int);
+
+        loop {
+            let result = unsafe { libc::poll(&mut fds, 1, timeout) };
+
+            // Signals (e.g. resizing the terminal) interrupt the waiting
+            if result < 0 && Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+
+            return result != 0;
+        }
+    }
+
+    /// Reads everything that is available into the queue. Returns false if the input was closed.
+    fn fill(&mut self) -> bool {
+        let mut buf = [0u8; 1024];
+        let len = unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+
+        if len <= 0 {
+            return len < 0 && Error::last_os_error().kind() == std::io::ErrorKind::Interrupted;
+        }
+
+        self.queue.extend(&buf[..len as usize]);
+
+        true
+    }
+
+    /// Gets the next event, waiting at most for the given time.
+    pub fn next(&mut self, timeout: Option<Duration>) -> Event {
+        loop {
+            let mut partial = false;
+
+            if !self.queue.is_empty() {
+                match self.parse() {
+                    Some(event) => return event,
+                    None => partial = true,
+                }
+            }
+
+            if partial && !self.poll(Some(SEQUENCE_TIMEOUT)) {
+                // The rest of the sequence never came, so an escape was a press of the escape key
+                // followed by other keys, other bytes can't be made sense of
+                match self.queue.pop_front() {
+                    Some(0x1b) => return Event::Key(Key::Esc),
+                    _ => continue,
+                }
+            }
+
+            if !partial && !self.poll(timeout) {
+                return Event::Idle;
+            }
+
+            if !self.fill() {
+                return Event::Closed;
+            }
+        }
+    }
+
+    /// Gets the next key, ignoring all other events.
+    pub fn next_key(&mut self) -> Option<Key> {
+        loop {
+            match self.next(None) {
+                Event::Key(key) => return Some(key),
+                Event::Closed => return None,
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether there is input that was not processed yet.
+    pub fn pending(&self) -> bool {
+        !self.queue.is_empty() || self.poll(Some(Duration::from_millis(0)))
+    }
+
+    /// Parses the first event in the queue. Returns `None` if the queue ends in the middle of
+    /// a sequence, the rest of it is still to be read.
+    fn parse(&mut self) -> Option<Event> {
+        if self.starts_with(b"\x1b[I") {
+            self.queue.drain(..3);
+            return Some(Event::FocusGained);
+        } else if self.starts_with(b"\x1b[O") {
+            self.queue.drain(..3);
+            return Some(Event::FocusLost);
+        }
+
+        let first = self.queue.pop_front().unwrap();
+
+        // An escape that is not followed by anything else was a single press of the escape key
+        if first == 0x1b && self.queue.is_empty() {
+            return Some(Event::Key(Key::Esc));
+        }
+
+        let mut consumed = 0;
+        let mut exhausted = false;
+        let result = {
+            let mut bytes = self.queue.iter();
+            let mut rest = std::iter::from_fn(|| match bytes.next() {
+                Some(&b) => {
+                    consumed += 1;
+                    Some(Ok(b))
+                }
+                None => {
+                    exhausted = true;
+                    None
+                }
+            });
+
+            event::parse_event(first, &mut rest)
+        };
+
+        match result {
+            Ok(event::Event::Key(key)) => {
+                self.queue.drain(..consumed);
+                Some(Event::Key(Key::from(key)))
+            }
+            _ if exhausted => {
+                self.queue.push_front(first);
+                None
+            }
+            // Unknown sequences are dropped, apart from their first byte
+            Ok(_) | Err(_) => {
+                if first == 0x1b {
+                    self.queue.drain(..consumed);
+                }
+
+                if self.queue.is_empty() {
+                    Some(Event::Idle)
+                } else {
+                    self.parse()
+                }
+            }
+        }
+    }
+
+    fn starts_with(&self, bytes: &[u8]) -> bool {
+        self.queue.len() >= bytes.len() && self.queue.iter().zip(bytes).all(|(a, b)| a == b)
+    }
+
+    /// Adds input to be processed as if it was typed, used for testing and for replaying keys.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.queue.extend(bytes);
+    }
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Enables or disables focus events on the given terminal.
+pub fn set_focus_events<W: Write>(out: &mut W, enabled: bool) {
+    let sequence = if enabled {
+        ENABLE_FOCUS_EVENTS
+    } else {
+        DISABLE_FOCUS_EVENTS
+    };
+
+    let _ = write!(out, "{}", sequence);
+    let _ = out.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let mut input = Input::new();
+        input.feed(b"a\x1b[I\x1b[A\x7f\x1b[O\x1b");
+
+        assert_eq!(Some(Event::Key(Key::Char('a'))), input.parse());
+        assert_eq!(Some(Event::FocusGained), input.parse());
+        assert_eq!(Some(Event::Key(Key::Up)), input.parse());
+        assert_eq!(Some(Event::Key(Key::Backspace)), input.parse());
+        assert_eq!(Some(Event::FocusLost), input.parse());
+        assert_eq!(Some(Event::Key(Key::Esc)), input.parse());
+    }
+
+    #[test]
+    fn split_sequence_test() {
+        let mut input = Input::new();
+
+        // Sequences split over two reads are kept until the rest arrives
+        input.feed(b"\xc3");
+        assert_eq!(None, input.parse());
+        input.feed(b"\xbc\x1b[");
+        assert_eq!(Some(Event::Key(Key::Char('\u{fc}'))), input.parse());
+        assert_eq!(None, input.parse());
+        input.feed(b"A");
+        assert_eq!(Some(Event::Key(Key::Up)), input.parse());
+    }
+
+    #[test]
+    fn sequence_timeout_test() {
+        // An input that never gets anything to read
+        let mut fds = [0; 2];
+        assert_eq!(0, unsafe { libc::pipe(fds.as_mut_ptr()) });
+
+        let mut input = Input {
+            queue: VecDeque::new(),
+            fd: fds[0],
+        };
+
+        // A sequence that isn't completed in time was typed as single keys
+        input.feed(b"\x1bO");
+        assert_eq!(Event::Key(Key::Esc), input.next(None));
+        assert_eq!(Event::Key(Key::Char('O')), input.next(None));
+
+        input.feed(b"\xc3");
+        assert_eq!(Event::Idle, input.next(Some(Duration::from_millis(0))));
+
+        unsafe {
+            libc::close(fds[0]);
+            libc::close(fds[1]);
+        }
+    }
+}
@@ -1,57 +1,735 @@
-use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Write};
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::buffer::Buffer;
 use crate::editor::Editor;
+use crate::editorconfig;
+use crate::encoding;
+use crate::filetype;
+use crate::indent::Indent;
+use crate::settings::Value;
+use crate::util;
 
 pub trait IO {
     fn load(&mut self) -> std::io::Result<()>;
+    fn load_encoded(&mut self, encoding: Option<&str>) -> std::io::Result<()>;
+    fn load_bytes(&mut self, bytes: &[u8], encoding: Option<&str>) -> std::io::Result<()>;
+    fn load_new(&mut self);
     fn save(&mut self) -> std::io::Result<()>;
+    fn write_to(&self, file_name: &str) -> std::io::Result<bool>;
+    fn changed_on_disk(&self) -> bool;
+    fn check_disk_changes(&mut self);
+}
+
+/// Describes an I/O error for the user, without the OS error number.
+pub fn error_message(err: &Error) -> String {
+    let message = err.to_string();
+
+    match message.find(" (os error ") {
+        Some(index) => String::from(&message[..index]),
+        None => message,
+    }
+}
+
+/// Whether the current user may write to the file at the given path.
+#[cfg(unix)]
+pub fn is_writable(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    match std::ffi::CString::new(path.as_os_str().as_bytes()) {
+        Ok(path) => unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 },
+        Err(_) => false,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn is_writable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| !metadata.permissions().readonly())
+}
+
+/// What is known about a file on disk, used to notice when another program changes it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiskState {
+    pub mtime: Option<SystemTime>,
+    pub size: u64,
+    pub inode: u64,
+}
+
+impl DiskState {
+    /// Gets the state of the file at the given path, or `None` if there is no such file.
+    pub fn read(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+
+        Some(Self {
+            mtime: metadata.modified().ok(),
+            size: metadata.len(),
+            inode: inode(&metadata),
+        })
+    }
+}
+
+#[cfg(unix)]
+fn inode(metadata: &Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::ino(metadata)
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &Metadata) -> u64 {
+    0
+}
+
+/// Gets the characters that end a line in the given file format.
+pub fn line_ending(fileformat: &str) -> &'static str {
+    match fileformat {
+        "dos" => "\r\n",
+        "mac" => "\r",
+        _ => "\n",
+    }
+}
+
+/// Detects the file format of a text from its line endings. Files are only considered DOS files
+/// if all of their lines end in CRLF. Returns `None` if the text has no line endings at all.
+pub fn detect_fileformat(text: &str) -> Option<&'static str> {
+    let newlines = text.matches('\n').count();
+    let crlfs = text.matches("\r\n").count();
+
+    if newlines > 0 && newlines == crlfs {
+        Some("dos")
+    } else if newlines > 0 {
+        Some("unix")
+    } else if text.contains('\r') {
+        Some("mac")
+    } else {
+        None
+    }
+}
+
+/// Splits a text into lines using the line ending of the given file format. Also returns whether
+/// the last line was terminated by a line ending.
+pub fn split_lines(text: &str, fileformat: &str) -> (Vec<String>, bool) {
+    let line_ending = line_ending(fileformat);
+    let endofline = text.ends_with(line_ending);
+    let text = text.strip_suffix(line_ending).unwrap_or(text);
+
+    let lines = text.split(line_ending).map(String::from).collect();
+
+    (lines, endofline)
+}
+
+/// Copies the version of a file that is currently on disk to a backup file. With `keep` set to
+/// zero there is a single backup file that is overwritten every time, otherwise the backup files
+/// are named by the time they were made and, with `prune` set, only the last `keep` ones are
+/// kept. Returns `None` if there is no file to back up yet.
+pub fn make_backup(
+    file_name: &str,
+    directory: &str,
+    extension: &str,
+    keep: usize,
+    prune: bool,
+) -> std::io::Result<Option<PathBuf>> {
+    // Back up the file itself rather than a symlink to it
+    let original = match fs::canonicalize(file_name) {
+        Ok(original) => original,
+        Err(_) => return Ok(None),
+    };
+
+    let base = util::companion_path(&original.to_string_lossy(), directory, false);
+    if let Some(dir) = base.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let base_name = base.file_name().unwrap().to_string_lossy().into_owned();
+
+    let backup = if keep == 0 {
+        base.with_file_name(format!("{}{}", base_name, extension))
+    } else {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or(0);
+
+        let name = format!("{}.{}", base_name, util::format_timestamp(time));
+
+        // Backups made within the same second get a counter
+        (0..)
+            .map(|n| match n {
+                0 => base.with_file_name(format!("{}{}", name, extension)),
+                n => base.with_file_name(format!("{}.{}{}", name, n, extension)),
+            })
+            .find(|path| !path.exists())
+            .unwrap()
+    };
+
+    // A backup in place of the file itself would destroy it
+    if fs::canonicalize(&backup).is_ok_and(|backup| backup == original) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "The backup file would be the file itself",
+        ));
+    }
+
+    fs::copy(&original, &backup)?;
+
+    if keep > 0 && prune {
+        prune_backups(&base, extension, keep)?;
+    }
+
+    Ok(Some(backup))
+}
+
+/// Removes all but the newest `keep` timestamped backup files of a file.
+fn prune_backups(base: &Path, extension: &str, keep: usize) -> std::io::Result<()> {
+    let dir = base.parent().unwrap();
+    let prefix = format!("{}.", base.file_name().unwrap().to_string_lossy());
+
+    let mut backups: Vec<(String, usize, PathBuf)> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter_map(|path| {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let rest = name.strip_prefix(&prefix)?.strip_suffix(extension)?;
+            let (time, counter) = match rest.split_once('.') {
+                Some((time, counter)) => (time, counter.parse().ok()?),
+                None => (rest, 0),
+            };
+
+            // The timestamp looks like `YYYYMMDD-HHMMSS`
+            if time.len() == 15 && time.chars().nth(8) == Some('-') {
+                Some((String::from(time), counter, path))
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    // Timestamps sort chronologically, so the oldest backups come first
+    backups.sort();
+
+    for (_, _, backup) in backups.iter().take(backups.len().saturating_sub(keep)) {
+        fs::remove_file(backup)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the content of a file to disk. Unless `backupcopy` asks for the file to be overwritten
+/// in place, the content is written to a temporary file first, which then replaces the original,
+/// so that a crash while writing never leaves a truncated file behind.
+pub fn write_file(path: &Path, content: &[u8], backupcopy: &str) -> std::io::Result<()> {
+    // Symlinks are followed, so that the file they point to is replaced rather than the link
+    let path = match fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(_) => path.to_path_buf(),
+    };
+    let metadata = fs::metadata(&path).ok();
+
+    let in_place = match backupcopy {
+        "yes" => true,
+        "no" => false,
+        // Replacing a file with more than one hard link would break the links
+        _ => metadata
+            .as_ref()
+            .is_some_and(|metadata| link_count(metadata) > 1),
+    };
+
+    if in_place {
+        return write_in_place(&path, content);
+    }
+
+    match write_atomic(&path, content, metadata.as_ref()) {
+        // If no file can be created in the directory or the ownership can't be kept, the only
+        // way to save is to overwrite the file
+        Err(err) if err.kind() == ErrorKind::PermissionDenied => write_in_place(&path, content),
+        result => result,
+    }
+}
+
+fn write_in_place(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+
+    file.write_all(content)?;
+    file.sync_all()
+}
+
+fn write_atomic(path: &Path, content: &[u8], metadata: Option<&Metadata>) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .ok_or_else(|| Error::other("Invalid file name"))?
+        .to_string_lossy();
+
+    // Find a temporary file name in the same directory, so that it can be renamed atomically
+    let (temp_path, mut file) = (0..)
+        .map(|i| dir.join(format!(".{}.{}.{}.rydl", name, process::id(), i)))
+        .take(100)
+        .find_map(|temp_path| {
+            let file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&temp_path);
+
+            match file {
+                Ok(file) => Some(Ok((temp_path, file))),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => None,
+                Err(err) => Some(Err(err)),
+            }
+        })
+        .unwrap_or_else(|| Err(Error::other("Could not create temporary file")))?;
+
+    let result = file
+        .write_all(content)
+        .and_then(|_| file.sync_all())
+        .and_then(|_| match metadata {
+            Some(metadata) => copy_metadata(path, &temp_path, metadata),
+            None => Ok(()),
+        })
+        .and_then(|_| fs::rename(&temp_path, path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+        return result;
+    }
+
+    // Make sure the rename itself is on disk, this is not supported everywhere
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+
+    Ok(())
+}
+
+/// Carries over the permissions, ownership and extended attributes of the original file to the
+/// file replacing it.
+fn copy_metadata(original: &Path, new: &Path, metadata: &Metadata) -> std::io::Result<()> {
+    fs::set_permissions(new, metadata.permissions())?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        // Changing the owner is only allowed for root, the new file already belongs to the
+        // current user otherwise. If that is not the owner of the original file, it must be
+        // overwritten in place instead.
+        let new_metadata = fs::metadata(new)?;
+        if new_metadata.uid() != metadata.uid() || new_metadata.gid() != metadata.gid() {
+            std::os::unix::fs::chown(new, Some(metadata.uid()), Some(metadata.gid()))?;
+        }
+    }
+
+    if xattr::SUPPORTED_PLATFORM {
+        if let Ok(names) = xattr::list(original) {
+            for name in names {
+                if let Ok(Some(value)) = xattr::get(original, &name) {
+                    // Some attributes (e.g. security labels) can't be set by every user
+                    let _ = xattr::set(new, &name, &value);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn link_count(metadata: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.nlink()
+}
+
+#[cfg(not(unix))]
+fn link_count(_metadata: &Metadata) -> u64 {
+    1
 }
 
 impl IO for Editor {
     fn load(&mut self) -> std::io::Result<()> {
+        self.load_encoded(None)
+    }
+
+    /// Loads the file into the buffer, decoding it from the given encoding or the detected one.
+    fn load_encoded(&mut self, encoding: Option<&str>) -> std::io::Result<()> {
         if self.file_name == String::new() {
-            return Err(Error::new(ErrorKind::Other, "No file name set in editor"));
+            return Err(Error::other("No file name set in editor"));
         }
 
-        self.buffer = Buffer::new(false);
+        let bytes = fs::read(&self.file_name)?;
 
-        let file = File::open(&self.file_name)?;
-        let buf = BufReader::new(file);
+        self.load_bytes(&bytes, encoding)?;
+        self.buffer.disk_state = DiskState::read(Path::new(&self.file_name));
 
-        for line in buf.lines() {
-            let mut line = line?;
-            line.retain(|c| c != '\n');
+        if !is_writable(Path::new(&self.file_name)) {
+            self.buffer
+                .options
+                .set_by_name("readonly", Value::Bool(true));
+        }
 
+        Ok(())
+    }
+
+    /// Loads the content of a file into the buffer, the file name is used to find the settings
+    /// for the file.
+    fn load_bytes(&mut self, bytes: &[u8], encoding: Option<&str>) -> std::io::Result<()> {
+        let (encoding, bom) = match encoding {
+            Some(encoding) => {
+                let bom = encoding::bom(encoding);
+
+                (encoding, !bom.is_empty() && bytes.starts_with(bom))
+            }
+            None => encoding::detect(bytes),
+        };
+        let bytes = if bom {
+            &bytes[encoding::bom(encoding).len()..]
+        } else {
+            bytes
+        };
+
+        let lossless = self.option("lossless").as_bool().unwrap();
+        let (text, invalid) = encoding::decode(bytes, encoding, lossless)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+
+        let fileformat = match detect_fileformat(&text) {
+            Some(fileformat) => String::from(fileformat),
+            None => String::from(self.option("fileformat").as_str().unwrap()),
+        };
+        let (lines, endofline) = split_lines(&text, &fileformat);
+
+        // The buffer is only replaced once the file could be read, so it is kept on errors
+        self.buffer = Buffer::new(false);
+
+        for line in lines {
             self.buffer.push(line);
         }
 
+        self.buffer.escaped = invalid > 0;
+
+        let options = &mut self.buffer.options;
+        options.set_by_name("fileformat", Value::Str(fileformat));
+        options.set_by_name("endofline", Value::Bool(endofline));
+        options.set_by_name("bomb", Value::Bool(bom));
+        options.set_by_name("fileencoding", Value::Str(String::from(encoding)));
+
+        let filetype = filetype::detect(&self.file_name);
+        self.buffer
+            .options
+            .set_by_name("filetype", Value::Str(String::from(filetype)));
+
+        if invalid > 0 {
+            self.show_warning(&format!(
+                "{} invalid {} bytes kept unchanged",
+                invalid, encoding
+            ));
+        }
+
+        if let Some(notice) = self.detect_indent() {
+            self.show_message(&notice);
+        }
+
+        if !self.file_name.is_empty() && self.option("editorconfig").as_bool().unwrap() {
+            let properties = editorconfig::properties_for(&self.file_name);
+            editorconfig::apply(&mut self.buffer.options, &properties);
+        }
+
         self.x = self.start_x();
         self.y = 1;
         self.current_char = 1;
         self.current_line = 1;
+        self.set_top_line(1);
         self.modified = false;
 
         Ok(())
     }
 
-    fn save(&mut self) -> std::io::Result<()> {
-        if self.file_name == String::new() {
-            return Err(Error::new(ErrorKind::Other, "No file name set in editor"));
+    /// Starts an empty buffer for a file that does not exist yet.
+    fn load_new(&mut self) {
+        self.buffer = Buffer::new(true);
+        self.buffer.is_new = true;
+
+        let filetype = filetype::detect(&self.file_name);
+        self.buffer
+            .options
+            .set_by_name("filetype", Value::Str(String::from(filetype)));
+
+        if self.option("editorconfig").as_bool().unwrap() {
+            let properties = editorconfig::properties_for(&self.file_name);
+            editorconfig::apply(&mut self.buffer.options, &properties);
         }
 
-        let file = File::create(&self.file_name)?;
-        let mut buf = BufWriter::new(file);
+        self.x = self.start_x();
+        self.y = 1;
+        self.current_char = 1;
+        self.current_line = 1;
+        self.set_top_line(1);
+        self.modified = false;
+    }
 
-        for line in self.buffer.iter() {
-            writeln!(buf, "{}", line)?;
+    fn save(&mut self) -> std::io::Result<()> {
+        if self.file_name == String::new() {
+            return Err(Error::other("No file name set in editor"));
         }
 
-        buf.flush()?;
+        let final_newline = self.write_to(&self.file_name)?;
 
+        self.buffer
+            .options
+            .set_by_name("endofline", Value::Bool(final_newline));
+        self.buffer.disk_state = DiskState::read(Path::new(&self.file_name));
+        self.buffer.disk_change_shown = false;
+        self.buffer.is_new = false;
         self.modified = false;
 
         Ok(())
     }
+
+    /// Writes the buffer to the given file without changing the state of the buffer. Returns
+    /// whether the file was written with a final newline.
+    fn write_to(&self, file_name: &str) -> std::io::Result<bool> {
+        let line_ending = line_ending(self.option("fileformat").as_str().unwrap());
+        let trim = self.option("trimwhitespace").as_bool().unwrap();
+        let endofline = self.option("endofline").as_bool().unwrap();
+
+        // A buffer with a single empty line and no final newline comes from an empty file, so
+        // it is not fixed to contain a newline
+        let empty = self.buffer.len() == 1 && self.buffer.get(1).unwrap().is_empty();
+        let final_newline = endofline || (!empty && self.option("fixendofline").as_bool().unwrap());
+
+        let mut text = String::new();
+
+        for (i, line) in self.buffer.iter().enumerate() {
+            text.push_str(if trim { line.trim_end() } else { line });
+
+            if i + 1 < self.buffer.len() || final_newline {
+                text.push_str(line_ending);
+            }
+        }
+
+        let encoding = self.option("fileencoding");
+        let encoding = encoding.as_str().unwrap();
+        let bytes = encoding::encode(&text, encoding, self.buffer.escaped)
+            .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+
+        let mut content = Vec::new();
+
+        if self.option("bomb").as_bool().unwrap() {
+            content.extend_from_slice(encoding::bom(encoding));
+        }
+
+        content.extend_from_slice(&bytes);
+
+        let keep_backup = self.option("backup").as_bool().unwrap();
+        let backup = if keep_backup || self.option("writebackup").as_bool().unwrap() {
+            let directory = self.option("backupdir");
+            let extension = self.option("backupext");
+
+            make_backup(
+                file_name,
+                directory.as_str().unwrap(),
+                extension.as_str().unwrap(),
+                self.option("backupkeep").as_int().unwrap(),
+                keep_backup,
+            )
+            .map_err(|err| Error::new(err.kind(), format!("Cannot create backup file: {}", err)))?
+        } else {
+            None
+        };
+
+        let backupcopy = self.option("backupcopy");
+        write_file(Path::new(file_name), &content, backupcopy.as_str().unwrap())?;
+
+        // With only `writebackup` set, the backup is just kept while writing
+        if let (Some(backup), false) = (backup, keep_backup) {
+            let _ = fs::remove_file(backup);
+        }
+
+        Ok(final_newline)
+    }
+
+    /// Whether the file was changed by another program since it was last read or written. A file
+    /// that was deleted doesn't count, writing it loses nothing.
+    fn changed_on_disk(&self) -> bool {
+        match (
+            &self.buffer.disk_state,
+            DiskState::read(Path::new(&self.file_name)),
+        ) {
+            (Some(state), Some(current)) => current != *state,
+            _ => false,
+        }
+    }
+
+    /// Reloads the file if it was changed on disk and `autoread` is set, otherwise tells the
+    /// user about the change once.
+    fn check_disk_changes(&mut self) {
+        if self.buffer.disk_change_shown || !self.changed_on_disk() {
+            return;
+        }
+
+        if !self.modified && self.option("autoread").as_bool().unwrap() {
+            match self.reload(None) {
+                Ok(()) => self.show_message("File changed on disk, reloaded"),
+                Err(err) => self.show_error(&err.to_string()),
+            }
+
+            return;
+        }
+
+        self.buffer.disk_change_shown = true;
+        self.show_warning("File changed on disk, :e! to reload or :w! to overwrite");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_fileformat_test() {
+        assert_eq!(Some("unix"), detect_fileformat("a\nb\n"));
+        assert_eq!(Some("dos"), detect_fileformat("a\r\nb\r\n"));
+        assert_eq!(Some("unix"), detect_fileformat("a\r\nb\n"));
+        assert_eq!(Some("mac"), detect_fileformat("a\rb\r"));
+        assert_eq!(None, detect_fileformat("a"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn write_file_test() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = util::TempDir::new("write-file");
+
+        let path = dir.join("file.txt");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let link = dir.join("link.txt");
+        symlink(&path, &link).unwrap();
+
+        write_file(&link, b"new", "auto").unwrap();
+
+        assert_eq!("new", fs::read_to_string(&path).unwrap());
+        assert_eq!(
+            0o640,
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777
+        );
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(2, fs::read_dir(dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn load_bytes_test() {
+        let mut editor = Editor::headless();
+
+        editor.load_bytes(b"abc\n", Some("latin1")).unwrap();
+        assert_eq!(Value::Bool(false), editor.option("bomb"));
+
+        editor
+            .load_bytes(b"\xef\xbb\xbfabc\n", Some("utf-8"))
+            .unwrap();
+        assert_eq!(Value::Bool(true), editor.option("bomb"));
+        assert_eq!("abc", editor.buffer.get(1).unwrap());
+    }
+
+    #[test]
+    fn error_message_test() {
+        let err = fs::read("/nonexistent/rydl/file").unwrap_err();
+        assert_eq!("No such file or directory", error_message(&err));
+
+        let err = Error::other("Too many swap files");
+        assert_eq!("Too many swap files", error_message(&err));
+    }
+
+    #[test]
+    fn disk_state_test() {
+        let dir = util::TempDir::new("disk-state");
+
+        let path = dir.join("file.txt");
+        assert_eq!(None, DiskState::read(&path));
+        assert!(!is_writable(&path));
+
+        fs::write(&path, "old").unwrap();
+        let state = DiskState::read(&path).unwrap();
+        assert_eq!(Some(&state), DiskState::read(&path).as_ref());
+        assert!(is_writable(&path));
+
+        // Replacing the file changes its inode even if size and time stay the same
+        write_file(&path, b"new", "no").unwrap();
+        assert_ne!(Some(state), DiskState::read(&path));
+    }
+
+    #[test]
+    fn make_backup_test() {
+        let dir = util::TempDir::new("make-backup");
+
+        let path = dir.join("file.txt");
+        let path = path.to_str().unwrap();
+        fs::write(path, "old").unwrap();
+
+        let backup = make_backup(path, ".", "~", 0, true).unwrap().unwrap();
+        assert_eq!(dir.join("file.txt~"), backup);
+        assert_eq!("old", fs::read_to_string(&backup).unwrap());
+
+        let backup_dir = dir.join("backups");
+        let backup = make_backup(path, backup_dir.to_str().unwrap(), "~", 2, true).unwrap();
+        assert!(backup.unwrap().starts_with(&backup_dir));
+
+        // A second backup within the same second doesn't overwrite the first one
+        let first = make_backup(path, backup_dir.to_str().unwrap(), "~", 3, true).unwrap();
+        let second = make_backup(path, backup_dir.to_str().unwrap(), "~", 3, true).unwrap();
+        assert_ne!(first, second);
+        assert!(first.unwrap().exists());
+
+        // Older backups beyond the ones to keep are removed
+        let original = fs::canonicalize(path).unwrap();
+        let base = util::companion_path(
+            original.to_str().unwrap(),
+            backup_dir.to_str().unwrap(),
+            false,
+        );
+        let old = format!("{}.20000101-000000~", base.to_string_lossy());
+        fs::write(&old, "older").unwrap();
+
+        // Only backups that are kept are pruned
+        make_backup(path, backup_dir.to_str().unwrap(), "~", 1, false).unwrap();
+        assert!(Path::new(&old).exists());
+
+        make_backup(path, backup_dir.to_str().unwrap(), "~", 1, true).unwrap();
+        assert!(!Path::new(&old).exists());
+        assert_eq!(1, fs::read_dir(&backup_dir).unwrap().count());
+
+        assert_eq!(
+            None,
+            make_backup(&format!("{}.missing", path), ".", "~", 0, true).unwrap()
+        );
+
+        // Without an extension the backup would overwrite the file
+        assert!(make_backup(path, ".", "", 0, true).is_err());
+        assert_eq!("old", fs::read_to_string(path).unwrap());
+    }
+
+    #[test]
+    fn split_lines_test() {
+        let lines = |lines: &[&str]| lines.iter().map(|&l| String::from(l)).collect();
+
+        assert_eq!((lines(&["a", "b"]), true), split_lines("a\r\nb\r\n", "dos"));
+        assert_eq!((lines(&["a", "b"]), false), split_lines("a\nb", "unix"));
+        assert_eq!(
+            (lines(&["a\r", "b"]), true),
+            split_lines("a\r\nb\n", "unix")
+        );
+        assert_eq!((lines(&[""]), false), split_lines("", "unix"));
+        assert_eq!((lines(&[""]), true), split_lines("\n", "unix"));
+    }
 }
@@ -0,0 +1,49 @@
+/// A key press, independent of the terminal library that reported it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    Backspace,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Delete,
+    Insert,
+    /// A function key, e.g. `F(1)` for F1
+    F(u8),
+    Char(char),
+    Alt(char),
+    Ctrl(char),
+    Null,
+    Esc,
+}
+
+#[cfg(feature = "terminal")]
+impl From<termion::event::Key> for Key {
+    fn from(key: termion::event::Key) -> Self {
+        use termion::event::Key as TermKey;
+
+        match key {
+            TermKey::Backspace => Key::Backspace,
+            TermKey::Left => Key::Left,
+            TermKey::Right => Key::Right,
+            TermKey::Up => Key::Up,
+            TermKey::Down => Key::Down,
+            TermKey::Home => Key::Home,
+            TermKey::End => Key::End,
+            TermKey::PageUp => Key::PageUp,
+            TermKey::PageDown => Key::PageDown,
+            TermKey::Delete => Key::Delete,
+            TermKey::Insert => Key::Insert,
+            TermKey::F(n) => Key::F(n),
+            TermKey::Char(c) => Key::Char(c),
+            TermKey::Alt(c) => Key::Alt(c),
+            TermKey::Ctrl(c) => Key::Ctrl(c),
+            TermKey::Esc => Key::Esc,
+            _ => Key::Null,
+        }
+    }
+}
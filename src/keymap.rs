@@ -0,0 +1,471 @@
+use std::collections::VecDeque;
+
+use crate::error::{Error, Result};
+use crate::key::Key;
+
+/// How often a mapping may lead to another one before it is considered recursive.
+pub const MAX_DEPTH: usize = 1000;
+
+/// The modes mappings can be defined for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MapMode {
+    Normal,
+    Insert,
+    Visual,
+    CommandLine,
+}
+
+impl MapMode {
+    /// Gets the letter the mode is shown with in `:map`.
+    pub fn letter(self) -> char {
+        match self {
+            Self::Normal => 'n',
+            Self::Insert => 'i',
+            Self::Visual => 'v',
+            Self::CommandLine => 'c',
+        }
+    }
+}
+
+/// The kinds of mapping commands, e.g. `:nmap`, `:nnoremap` and `:nunmap`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MapCommand {
+    Map,
+    NoRemap,
+    Unmap,
+}
+
+/// Finds out what a command like `:nnoremap` or `:map!` does and which modes it is about.
+/// Without a mode letter it is about Normal and Visual mode, with a `!` about Insert and
+/// Command-line mode.
+pub fn parse_command(name: &str) -> Option<(MapCommand, &'static [MapMode])> {
+    let (name, bang) = match name.strip_suffix('!') {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+
+    let (command, mode) = match name {
+        "map" => (MapCommand::Map, None),
+        "no" | "noremap" => (MapCommand::NoRemap, None),
+        "unm" | "unmap" => (MapCommand::Unmap, None),
+        "nm" | "nmap" => (MapCommand::Map, Some('n')),
+        "nn" | "nnoremap" => (MapCommand::NoRemap, Some('n')),
+        "nun" | "nunmap" => (MapCommand::Unmap, Some('n')),
+        "im" | "imap" => (MapCommand::Map, Some('i')),
+        "ino" | "inoremap" => (MapCommand::NoRemap, Some('i')),
+        "iu" | "iunmap" => (MapCommand::Unmap, Some('i')),
+        "vm" | "vmap" => (MapCommand::Map, Some('v')),
+        "vn" | "vnoremap" => (MapCommand::NoRemap, Some('v')),
+        "vu" | "vunmap" => (MapCommand::Unmap, Some('v')),
+        "cm" | "cmap" => (MapCommand::Map, Some('c')),
+        "cno" | "cnoremap" => (MapCommand::NoRemap, Some('c')),
+        "cu" | "cunmap" => (MapCommand::Unmap, Some('c')),
+        _ => return None,
+    };
+
+    let modes: &'static [MapMode] = match (mode, bang) {
+        (None, false) => &[MapMode::Normal, MapMode::Visual],
+        (None, true) => &[MapMode::Insert, MapMode::CommandLine],
+        (Some(_), true) => return None,
+        (Some('n'), false) => &[MapMode::Normal],
+        (Some('i'), false) => &[MapMode::Insert],
+        (Some('v'), false) => &[MapMode::Visual],
+        (Some(_), false) => &[MapMode::CommandLine],
+    };
+
+    Some((command, modes))
+}
+
+/// What a mapping expands to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Rhs {
+    Keys(Vec<Key>),
+    /// An Ex command, written as `<Cmd>command<CR>`
+    Command(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mapping {
+    pub mode: MapMode,
+    pub lhs: Vec<Key>,
+    pub rhs: Rhs,
+    /// Whether the keys of the right-hand side are used as they are, without mapping them again
+    pub noremap: bool,
+}
+
+/// What the keys at the start of the typeahead turned out to be.
+#[derive(Debug, PartialEq)]
+pub enum Resolved {
+    Key(Key),
+    Command(String),
+    /// The keys could be the start of a mapping, so more are needed
+    Wait,
+}
+
+/// The mappings of all modes.
+#[derive(Default)]
+pub struct Keymap {
+    mappings: Vec<Mapping>,
+}
+
+impl Keymap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a mapping, replacing one with the same keys in the same mode.
+    pub fn map(&mut self, mapping: Mapping) {
+        self.unmap(mapping.mode, &mapping.lhs);
+        self.mappings.push(mapping);
+    }
+
+    /// Removes a mapping, returns whether there was one.
+    pub fn unmap(&mut self, mode: MapMode, lhs: &[Key]) -> bool {
+        let len = self.mappings.len();
+        self.mappings.retain(|m| m.mode != mode || m.lhs != lhs);
+
+        self.mappings.len() != len
+    }
+
+    /// Gets the mappings of a mode whose keys start with the given ones.
+    pub fn find(&self, mode: MapMode, prefix: &[Key]) -> Vec<&Mapping> {
+        self.mappings
+            .iter()
+            .filter(|m| m.mode == mode && m.lhs.starts_with(prefix))
+            .collect()
+    }
+
+    /// Takes the next key from the typeahead, expanding mappings at its start. Keys that come
+    /// from a `noremap` mapping are marked as not to be mapped again. If the keys might be the
+    /// start of a longer mapping, nothing is taken unless `timed_out` is set.
+    pub fn resolve(
+        &self,
+        mode: MapMode,
+        typeahead: &mut VecDeque<(Key, bool)>,
+        timed_out: bool,
+    ) -> Result<Option<Resolved>> {
+        for _ in 0..MAX_DEPTH {
+            let (first, remap) = match typeahead.front() {
+                Some(&front) => front,
+                None => return Ok(None),
+            };
+
+            if !remap {
+                typeahead.pop_front();
+                return Ok(Some(Resolved::Key(first)));
+            }
+
+            let keys: Vec<Key> = typeahead
+                .iter()
+                .take_while(|(_, remap)| *remap)
+                .map(|(key, _)| *key)
+                .collect();
+            let candidates = self.find(mode, &keys[..1]);
+
+            let longer = candidates
+                .iter()
+                .any(|m| m.lhs.len() > keys.len() && m.lhs.starts_with(&keys));
+
+            if longer && !timed_out {
+                return Ok(Some(Resolved::Wait));
+            }
+
+            let mapping = candidates
+                .into_iter()
+                .filter(|m| keys.starts_with(&m.lhs))
+                .max_by_key(|m| m.lhs.len());
+
+            let mapping = match mapping {
+                Some(mapping) => mapping,
+                None => {
+                    typeahead.pop_front();
+                    return Ok(Some(Resolved::Key(first)));
+                }
+            };
+
+            typeahead.drain(..mapping.lhs.len());
+
+            match &mapping.rhs {
+                Rhs::Command(command) => return Ok(Some(Resolved::Command(command.clone()))),
+                Rhs::Keys(rhs) => {
+                    // Like in `:nmap x xy`, a right-hand side starting with the left-hand side
+                    // doesn't map it again
+                    let own_start = if rhs.starts_with(&mapping.lhs) {
+                        mapping.lhs.len()
+                    } else {
+                        0
+                    };
+
+                    for (i, key) in rhs.iter().enumerate().rev() {
+                        typeahead.push_front((*key, !mapping.noremap && i >= own_start));
+                    }
+                }
+            }
+        }
+
+        typeahead.clear();
+
+        Err(Error::RecursiveMapping)
+    }
+}
+
+/// Parses keys written in the notation of mappings, e.g. `<C-s>`, `<Esc>` or `<leader>w`.
+/// Unknown names in angle brackets are taken literally.
+pub fn parse_keys(notation: &str, leader: &str) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut rest = notation;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(end) = rest.find('>') {
+                let name = &rest[1..end];
+
+                if name.eq_ignore_ascii_case("leader") {
+                    keys.extend(leader.chars().map(Key::Char));
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+
+                if let Some(key) = parse_key_name(name) {
+                    keys.push(key);
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+            }
+        }
+
+        keys.push(Key::Char(c));
+        rest = &rest[c.len_utf8()..];
+    }
+
+    keys
+}
+
+/// Parses the name of a key between angle brackets.
+fn parse_key_name(name: &str) -> Option<Key> {
+    let lower = name.to_ascii_lowercase();
+
+    let key = match lower.as_str() {
+        "cr" | "enter" | "return" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        "esc" => Key::Esc,
+        "bs" | "backspace" => Key::Backspace,
+        "space" => Key::Char(' '),
+        "lt" => Key::Char('<'),
+        "bar" => Key::Char('|'),
+        "bslash" => Key::Char('\\'),
+        "del" | "delete" => Key::Delete,
+        "ins" | "insert" => Key::Insert,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "nul" => Key::Null,
+        _ => {
+            if let Some(number) = lower.strip_prefix('f') {
+                return number
+                    .parse()
+                    .ok()
+                    .filter(|n| (1..=12).contains(n))
+                    .map(Key::F);
+            }
+
+            let (modifier, rest) = lower.split_at(lower.find('-')? + 1);
+            let mut chars = rest.chars();
+            let c = chars.next()?;
+
+            if chars.next().is_some() {
+                return None;
+            }
+
+            return match modifier {
+                "c-" => Some(Key::Ctrl(c)),
+                "m-" | "a-" => Some(Key::Alt(c)),
+                _ => None,
+            };
+        }
+    };
+
+    Some(key)
+}
+
+/// Writes keys in the notation of mappings, the opposite of `parse_keys`.
+pub fn key_notation(keys: &[Key]) -> String {
+    keys.iter()
+        .map(|key| match key {
+            Key::Char('\n') => String::from("<CR>"),
+            Key::Char('\t') => String::from("<Tab>"),
+            Key::Char(' ') => String::from("<Space>"),
+            Key::Char('<') => String::from("<lt>"),
+            Key::Char(c) => c.to_string(),
+            Key::Ctrl(c) => format!("<C-{}>", c),
+            Key::Alt(c) => format!("<M-{}>", c),
+            Key::F(n) => format!("<F{}>", n),
+            Key::Esc => String::from("<Esc>"),
+            Key::Backspace => String::from("<BS>"),
+            Key::Delete => String::from("<Del>"),
+            Key::Insert => String::from("<Insert>"),
+            Key::Up => String::from("<Up>"),
+            Key::Down => String::from("<Down>"),
+            Key::Left => String::from("<Left>"),
+            Key::Right => String::from("<Right>"),
+            Key::Home => String::from("<Home>"),
+            Key::End => String::from("<End>"),
+            Key::PageUp => String::from("<PageUp>"),
+            Key::PageDown => String::from("<PageDown>"),
+            Key::Null => String::from("<Nul>"),
+        })
+        .collect()
+}
+
+/// Parses the right-hand side of a mapping.
+pub fn parse_rhs(notation: &str, leader: &str) -> Rhs {
+    let lower = notation.to_ascii_lowercase();
+
+    if lower.starts_with("<cmd>") && lower.ends_with("<cr>") {
+        Rhs::Command(String::from(&notation[5..notation.len() - 4]))
+    } else {
+        Rhs::Keys(parse_keys(notation, leader))
+    }
+}
+
+impl Mapping {
+    /// Formats the mapping like `:map` lists it.
+    pub fn describe(&self) -> String {
+        let rhs = match &self.rhs {
+            Rhs::Keys(keys) => key_notation(keys),
+            Rhs::Command(command) => format!("<Cmd>{}<CR>", command),
+        };
+
+        format!(
+            "{}  {:<12} {}{}",
+            self.mode.letter(),
+            key_notation(&self.lhs),
+            if self.noremap { "* " } else { "  " },
+            rhs
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typeahead(keys: &[Key]) -> VecDeque<(Key, bool)> {
+        keys.iter().map(|key| (*key, true)).collect()
+    }
+
+    fn map(keymap: &mut Keymap, lhs: &str, rhs: &str, noremap: bool) {
+        keymap.map(Mapping {
+            mode: MapMode::Normal,
+            lhs: parse_keys(lhs, "\\"),
+            rhs: parse_rhs(rhs, "\\"),
+            noremap,
+        });
+    }
+
+    #[test]
+    fn parse_keys_test() {
+        assert_eq!(
+            vec![
+                Key::Char(','),
+                Key::Char('w'),
+                Key::Ctrl('s'),
+                Key::Esc,
+                Key::Char('\n'),
+                Key::Char('<'),
+                Key::Char('x'),
+                Key::F(5),
+            ],
+            parse_keys("<leader>w<C-s><esc><CR><lt>x<F5>", ",")
+        );
+        assert_eq!(
+            vec![Key::Char('<'), Key::Char('a'), Key::Char('>')],
+            parse_keys("<a>", ",")
+        );
+        assert_eq!(
+            "<C-s><Space>x<CR>",
+            key_notation(&parse_keys("<C-S> x<cr>", ","))
+        );
+        assert_eq!(
+            Rhs::Command(String::from("w")),
+            parse_rhs("<Cmd>w<CR>", ",")
+        );
+    }
+
+    #[test]
+    fn parse_command_test() {
+        assert_eq!(
+            Some((MapCommand::NoRemap, &[MapMode::Normal][..])),
+            parse_command("nnoremap")
+        );
+        assert_eq!(
+            Some((
+                MapCommand::Map,
+                &[MapMode::Insert, MapMode::CommandLine][..]
+            )),
+            parse_command("map!")
+        );
+        assert_eq!(None, parse_command("nmap!"));
+        assert_eq!(None, parse_command("write"));
+    }
+
+    #[test]
+    fn resolve_test() {
+        let mut keymap = Keymap::new();
+        map(&mut keymap, "jk", "<Esc>", false);
+        map(&mut keymap, "Q", "jk", false);
+        map(&mut keymap, "x", "xl", false);
+        map(&mut keymap, "W", "<Cmd>w<CR>", true);
+        map(&mut keymap, "a", "b", false);
+        map(&mut keymap, "b", "a", false);
+
+        let mut keys = typeahead(&[Key::Char('j')]);
+        assert_eq!(
+            Some(Resolved::Wait),
+            keymap.resolve(MapMode::Normal, &mut keys, false).unwrap()
+        );
+        // After the timeout the key is taken as it is
+        assert_eq!(
+            Some(Resolved::Key(Key::Char('j'))),
+            keymap.resolve(MapMode::Normal, &mut keys, true).unwrap()
+        );
+
+        let mut keys = typeahead(&[Key::Char('Q')]);
+        assert_eq!(
+            Some(Resolved::Key(Key::Esc)),
+            keymap.resolve(MapMode::Normal, &mut keys, false).unwrap()
+        );
+
+        let mut keys = typeahead(&[Key::Char('x')]);
+        let mut resolved = Vec::new();
+        while let Ok(Some(Resolved::Key(key))) = keymap.resolve(MapMode::Normal, &mut keys, false) {
+            resolved.push(key);
+        }
+        assert_eq!(vec![Key::Char('x'), Key::Char('l')], resolved);
+
+        let mut keys = typeahead(&[Key::Char('W')]);
+        assert_eq!(
+            Some(Resolved::Command(String::from("w"))),
+            keymap.resolve(MapMode::Normal, &mut keys, false).unwrap()
+        );
+
+        // Mappings only apply in their mode
+        let mut keys = typeahead(&[Key::Char('Q')]);
+        assert_eq!(
+            Some(Resolved::Key(Key::Char('Q'))),
+            keymap.resolve(MapMode::Insert, &mut keys, false).unwrap()
+        );
+
+        let mut keys = typeahead(&[Key::Char('a')]);
+        assert!(matches!(
+            keymap.resolve(MapMode::Normal, &mut keys, false),
+            Err(Error::RecursiveMapping)
+        ));
+        assert!(keys.is_empty());
+    }
+}
@@ -0,0 +1,33 @@
+//! The editing core of rydl: buffers, the cursor, Ex commands, file I/O and settings.
+//!
+//! Everything that talks to the terminal is behind the `terminal` feature, which is enabled by
+//! default. Without it an [`Editor`](editor::Editor) can still be created with
+//! [`Editor::headless`](editor::Editor::headless) and driven through Ex commands, e.g. to embed
+//! rydl's editing in other programs or to test it.
+
+pub mod args;
+pub mod buffer;
+pub mod cmdline;
+pub mod editor;
+pub mod editorconfig;
+pub mod encoding;
+pub mod error;
+pub mod filetype;
+pub mod handler;
+pub mod indent;
+pub mod io;
+pub mod key;
+pub mod keymap;
+pub mod message;
+pub mod register;
+pub mod settings;
+pub mod statusline;
+pub mod swap;
+pub mod util;
+
+#[cfg(feature = "terminal")]
+pub mod drawer;
+#[cfg(feature = "terminal")]
+pub mod input;
+#[cfg(feature = "terminal")]
+pub mod terminal;
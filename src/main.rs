@@ -1,21 +1,108 @@
-#![allow(dead_code)]
+use std::env;
+use std::fs;
+use std::io::{stdin, stdout, Read, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::process;
 
-extern crate termion;
+use rydl::args::{self, Action, Args};
+use rydl::editor::Editor;
+use rydl::handler::Handler;
+use rydl::swap::Swap;
+use rydl::terminal;
 
-mod buffer;
-mod drawer;
-mod editor;
-mod handler;
-mod io;
-mod settings;
-mod util;
+fn main() {
+    let args = match args::parse(env::args().skip(1)) {
+        Ok(Action::Edit(args)) => args,
+        Ok(Action::Help) => {
+            // The help is often piped into a pager, which might be closed early
+            let _ = writeln!(stdout(), "{}", args::USAGE);
+            return;
+        }
+        Ok(Action::Version) => {
+            println!("rydl {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(err) => {
+            eprintln!("rydl: {}\nTry 'rydl --help' for more information.", err);
+            process::exit(2);
+        }
+    };
 
-use crate::editor::Editor;
-use crate::handler::Handler;
+    // The buffer is read from the pipe, keys are then read from the terminal
+    let stdin = if args.stdin {
+        let mut bytes = Vec::new();
+
+        if let Err(err) = stdin().read_to_end(&mut bytes) {
+            eprintln!("rydl: could not read standard input: {}", err);
+            process::exit(1);
+        }
+
+        if !args.batch {
+            if let Err(err) = terminal::reopen_stdin() {
+                eprintln!("rydl: could not open the terminal: {}", err);
+                process::exit(1);
+            }
+        }
+
+        Some(bytes)
+    } else {
+        None
+    };
+
+    if args.batch {
+        run_batch(args, stdin);
+    }
+
+    terminal::install_panic_hook();
 
-fn main() {
     let mut editor = Editor::new();
+    let guard = terminal::Guard::new();
 
     editor.init();
-    editor.handle();
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        editor.start(args, stdin);
+        editor.handle();
+    }));
+
+    drop(guard);
+
+    // After a crash, the unsaved changes are still in the buffer
+    if result.is_err() {
+        if editor.modified {
+            match editor.write_emergency() {
+                Ok(path) => eprintln!("rydl: unsaved changes were written to {}", path.display()),
+                Err(err) => eprintln!("rydl: could not save unsaved changes: {}", err),
+            }
+        }
+
+        process::exit(101);
+    }
+}
+
+/// Runs Ex commands without using the terminal. Exits with a non-zero status if any command
+/// failed.
+fn run_batch(mut args: Args, stdin: Option<Vec<u8>>) -> ! {
+    let script = match args.script.take() {
+        Some(file_name) => fs::read_to_string(&file_name),
+        None => {
+            let mut script = String::new();
+            std::io::stdin().read_to_string(&mut script).map(|_| script)
+        }
+    };
+
+    let script = match script {
+        Ok(script) => script,
+        Err(err) => {
+            eprintln!("rydl: could not read the commands: {}", err);
+            process::exit(1);
+        }
+    };
+
+    let mut editor = Editor::headless();
+
+    editor.start(args, stdin);
+    editor.run_script(&script);
+
+    process::exit(if editor.error_count > 0 { 1 } else { 0 });
 }
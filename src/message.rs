@@ -0,0 +1,84 @@
+use std::collections::VecDeque;
+
+/// The number of messages kept for `:messages`.
+pub const HISTORY_SIZE: usize = 200;
+
+/// How important a message is, this decides the color it is shown in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// A message shown to the user in the message line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    pub text: String,
+    pub severity: Severity,
+}
+
+impl Message {
+    pub fn new(severity: Severity, text: &str) -> Self {
+        Self {
+            text: String::from(text),
+            severity,
+        }
+    }
+}
+
+/// The messages shown so far, the oldest ones are dropped once there are `HISTORY_SIZE` of them.
+#[derive(Default)]
+pub struct History {
+    messages: VecDeque<Message>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, message: Message) {
+        if self.messages.len() == HISTORY_SIZE {
+            self.messages.pop_front();
+        }
+
+        self.messages.push_back(message);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Message> {
+        self.messages.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.messages.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_test() {
+        let mut history = History::new();
+
+        for i in 0..HISTORY_SIZE + 2 {
+            history.push(Message::new(Severity::Info, &i.to_string()));
+        }
+
+        assert_eq!(HISTORY_SIZE, history.len());
+        assert_eq!("2", history.iter().next().unwrap().text);
+
+        history.clear();
+        assert!(history.is_empty());
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+/// The content of a register.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Register {
+    pub lines: Vec<String>,
+    /// Whether whole lines were yanked, these are put below the cursor line instead of into it
+    pub linewise: bool,
+}
+
+impl Register {
+    pub fn new(lines: Vec<String>, linewise: bool) -> Self {
+        Self { lines, linewise }
+    }
+
+    /// Gets the content as one text, with lines separated by newlines.
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+}
+
+/// The registers text is yanked into and put from. Besides the unnamed register `"` there are
+/// the named registers `a` to `z`, numbered registers `0` to `9` and the last command line `:`.
+#[derive(Default)]
+pub struct Registers {
+    registers: HashMap<char, Register>,
+}
+
+impl Registers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks whether a register with that name exists, upper case names refer to the named
+    /// registers as well.
+    pub fn is_valid(name: char) -> bool {
+        matches!(name, '"' | ':' | 'a'..='z' | 'A'..='Z' | '0'..='9')
+    }
+
+    pub fn get(&self, name: char) -> Option<&Register> {
+        self.registers.get(&name.to_ascii_lowercase())
+    }
+
+    /// Stores yanked text in the unnamed register and in register `0`.
+    pub fn yank(&mut self, register: Register) {
+        self.set('0', register.clone());
+        self.set('"', register);
+    }
+
+    /// Stores deleted text in the unnamed register. Deleted lines also go to register `1`, the
+    /// ones deleted before move on to `2` to `9`.
+    pub fn delete(&mut self, register: Register) {
+        if register.linewise || register.lines.len() > 1 {
+            for n in (1..9).rev() {
+                if let Some(older) = self.registers.remove(&numbered(n)) {
+                    self.registers.insert(numbered(n + 1), older);
+                }
+            }
+
+            self.set('1', register.clone());
+        }
+
+        self.set('"', register);
+    }
+
+    /// Stores a register, an upper case name appends to the named register instead.
+    pub fn set(&mut self, name: char, register: Register) {
+        if !Self::is_valid(name) {
+            return;
+        }
+
+        if name.is_ascii_uppercase() {
+            let existing = self.registers.entry(name.to_ascii_lowercase()).or_default();
+
+            existing.lines.extend(register.lines);
+            existing.linewise |= register.linewise;
+        } else {
+            self.registers.insert(name, register);
+        }
+    }
+}
+
+/// Gets the name of a numbered register.
+fn numbered(n: u32) -> char {
+    std::char::from_digit(n, 10).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_test() {
+        let mut registers = Registers::new();
+
+        registers.set('a', Register::new(vec![String::from("one")], false));
+        registers.set('A', Register::new(vec![String::from("two")], true));
+        registers.set('!', Register::new(vec![String::from("ignored")], false));
+
+        assert_eq!("one\ntwo", registers.get('a').unwrap().text());
+        assert!(registers.get('A').unwrap().linewise);
+        assert_eq!(None, registers.get('!'));
+    }
+
+    #[test]
+    fn yank_delete_test() {
+        let mut registers = Registers::new();
+        let lines = |text: &str| vec![String::from(text)];
+
+        registers.yank(Register::new(lines("yanked"), true));
+        registers.delete(Register::new(lines("first"), true));
+        registers.delete(Register::new(lines("second"), true));
+        registers.delete(Register::new(lines("x"), false));
+
+        assert_eq!("x", registers.get('"').unwrap().text());
+        assert_eq!("yanked", registers.get('0').unwrap().text());
+        assert_eq!("second", registers.get('1').unwrap().text());
+        assert_eq!("first", registers.get('2').unwrap().text());
+    }
+}
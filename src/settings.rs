@@ -1,19 +1,637 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::encoding;
+
 /// The tabulator type, can be either soft (spaces) or hard (tabs).
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum TabType {
     Soft,
     Hard,
 }
 
+/// Where the value of an option is stored.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scope {
+    Global,
+    Buffer,
+    Window,
+}
+
+/// The type of an option together with its default value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Bool(bool),
+    Int(usize),
+    /// A number that must be greater than zero
+    Positive(usize),
+    Str(&'static str),
+    /// A string that must not be empty
+    NonEmpty(&'static str),
+    Enum(&'static [&'static str], &'static str),
+    /// One of `encoding::ENCODINGS`, alternative names like `latin-1` are accepted as well
+    Encoding(&'static str),
+}
+
+/// The value of an option.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Int(usize),
+    Str(String),
+}
+
+impl Value {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<usize> {
+        match self {
+            Self::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Int(n) => write!(f, "{}", n),
+            Self::Str(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+/// The definition of an option in the registry.
+pub struct OptionDef {
+    pub name: &'static str,
+    pub short: Option<&'static str>,
+    pub scope: Scope,
+    pub kind: Kind,
+}
+
+impl OptionDef {
+    pub fn default_value(&self) -> Value {
+        match self.kind {
+            Kind::Bool(b) => Value::Bool(b),
+            Kind::Int(n) | Kind::Positive(n) => Value::Int(n),
+            Kind::Str(s) | Kind::NonEmpty(s) | Kind::Enum(_, s) | Kind::Encoding(s) => {
+                Value::Str(String::from(s))
+            }
+        }
+    }
+
+    /// Parses a value given as text (e.g. in `:set opt=val`) according to the type of the option.
+    pub fn parse_value(&self, text: &str) -> Result<Value, String> {
+        match self.kind {
+            Kind::Bool(_) => Err(format!("Invalid argument: {}={}", self.name, text)),
+            Kind::Int(_) => text
+                .parse()
+                .map(Value::Int)
+                .map_err(|_| format!("Number required after =: {}={}", self.name, text)),
+            Kind::Positive(_) => match text.parse() {
+                Ok(0) => Err(format!("Argument must be positive: {}={}", self.name, text)),
+                Ok(n) => Ok(Value::Int(n)),
+                Err(_) => Err(format!("Number required after =: {}={}", self.name, text)),
+            },
+            Kind::Str(_) => Ok(Value::Str(String::from(text))),
+            Kind::NonEmpty(_) if text.is_empty() => {
+                Err(format!("Argument must not be empty: {}=", self.name))
+            }
+            Kind::NonEmpty(_) => Ok(Value::Str(String::from(text))),
+            Kind::Encoding(_) => match encoding::normalize(text) {
+                Some(encoding) => Ok(Value::Str(String::from(encoding))),
+                None => Err(format!("Invalid argument: {}={}", self.name, text)),
+            },
+            Kind::Enum(variants, _) => {
+                if variants.contains(&text) {
+                    Ok(Value::Str(String::from(text)))
+                } else {
+                    Err(format!("Invalid argument: {}={}", self.name, text))
+                }
+            }
+        }
+    }
+}
+
+/// All options known to rydl.
+pub const OPTIONS: &[OptionDef] = &[
+    OptionDef {
+        name: "autoindent",
+        short: Some("ai"),
+        scope: Scope::Buffer,
+        kind: Kind::Bool(true),
+    },
+    OptionDef {
+        name: "autoread",
+        short: Some("ar"),
+        scope: Scope::Global,
+        kind: Kind::Bool(false),
+    },
+    OptionDef {
+        name: "backup",
+        short: Some("bk"),
+        scope: Scope::Global,
+        kind: Kind::Bool(false),
+    },
+    OptionDef {
+        name: "backupcopy",
+        short: Some("bkc"),
+        scope: Scope::Global,
+        kind: Kind::Enum(&["auto", "yes", "no"], "auto"),
+    },
+    OptionDef {
+        name: "backupdir",
+        short: Some("bdir"),
+        scope: Scope::Global,
+        kind: Kind::Str("."),
+    },
+    OptionDef {
+        name: "backupext",
+        short: Some("bex"),
+        scope: Scope::Global,
+        kind: Kind::NonEmpty("~"),
+    },
+    OptionDef {
+        name: "backupkeep",
+        short: None,
+        scope: Scope::Global,
+        kind: Kind::Int(0),
+    },
+    OptionDef {
+        name: "bomb",
+        short: None,
+        scope: Scope::Buffer,
+        kind: Kind::Bool(false),
+    },
+    OptionDef {
+        name: "detectindent",
+        short: None,
+        scope: Scope::Global,
+        kind: Kind::Bool(true),
+    },
+    OptionDef {
+        name: "directory",
+        short: Some("dir"),
+        scope: Scope::Global,
+        kind: Kind::Str("."),
+    },
+    OptionDef {
+        name: "editorconfig",
+        short: None,
+        scope: Scope::Global,
+        kind: Kind::Bool(true),
+    },
+    OptionDef {
+        name: "endofline",
+        short: Some("eol"),
+        scope: Scope::Buffer,
+        kind: Kind::Bool(true),
+    },
+    OptionDef {
+        name: "expandtab",
+        short: Some("et"),
+        scope: Scope::Buffer,
+        kind: Kind::Bool(true),
+    },
+    OptionDef {
+        name: "fileencoding",
+        short: Some("fenc"),
+        scope: Scope::Buffer,
+        kind: Kind::Encoding("utf-8"),
+    },
+    OptionDef {
+        name: "fileformat",
+        short: Some("ff"),
+        scope: Scope::Buffer,
+        kind: Kind::Enum(&["unix", "dos", "mac"], "unix"),
+    },
+    OptionDef {
+        name: "filetype",
+        short: Some("ft"),
+        scope: Scope::Buffer,
+        kind: Kind::Str(""),
+    },
+    OptionDef {
+        name: "fixendofline",
+        short: Some("fixeol"),
+        scope: Scope::Buffer,
+        kind: Kind::Bool(false),
+    },
+    OptionDef {
+        name: "history",
+        short: Some("hi"),
+        scope: Scope::Global,
+        kind: Kind::Int(200),
+    },
+    OptionDef {
+        name: "lossless",
+        short: None,
+        scope: Scope::Global,
+        kind: Kind::Bool(true),
+    },
+    OptionDef {
+        name: "mapleader",
+        short: None,
+        scope: Scope::Global,
+        kind: Kind::Str("\\"),
+    },
+    OptionDef {
+        name: "modifiable",
+        short: Some("ma"),
+        scope: Scope::Buffer,
+        kind: Kind::Bool(true),
+    },
+    OptionDef {
+        name: "readonly",
+        short: Some("ro"),
+        scope: Scope::Buffer,
+        kind: Kind::Bool(false),
+    },
+    OptionDef {
+        name: "shiftwidth",
+        short: Some("sw"),
+        scope: Scope::Buffer,
+        kind: Kind::Int(0),
+    },
+    OptionDef {
+        name: "smartindent",
+        short: Some("si"),
+        scope: Scope::Buffer,
+        kind: Kind::Bool(true),
+    },
+    OptionDef {
+        name: "softtabstop",
+        short: Some("sts"),
+        scope: Scope::Buffer,
+        kind: Kind::Int(0),
+    },
+    OptionDef {
+        name: "statusline",
+        short: Some("stl"),
+        scope: Scope::Window,
+        kind: Kind::Str("%M%f %m%r%n%=%y %{fileencoding} %{fileformat}  %l/%L %p  %c"),
+    },
+    OptionDef {
+        name: "swapfile",
+        short: Some("swf"),
+        scope: Scope::Buffer,
+        kind: Kind::Bool(true),
+    },
+    OptionDef {
+        name: "tabstop",
+        short: Some("ts"),
+        scope: Scope::Buffer,
+        kind: Kind::Int(4),
+    },
+    OptionDef {
+        name: "textwidth",
+        short: Some("tw"),
+        scope: Scope::Buffer,
+        kind: Kind::Int(0),
+    },
+    OptionDef {
+        name: "updatecount",
+        short: Some("uc"),
+        scope: Scope::Global,
+        kind: Kind::Int(200),
+    },
+    OptionDef {
+        name: "updatetime",
+        short: Some("ut"),
+        scope: Scope::Global,
+        kind: Kind::Positive(4000),
+    },
+    OptionDef {
+        name: "timeout",
+        short: Some("to"),
+        scope: Scope::Global,
+        kind: Kind::Bool(true),
+    },
+    OptionDef {
+        name: "timeoutlen",
+        short: Some("tm"),
+        scope: Scope::Global,
+        kind: Kind::Int(1000),
+    },
+    OptionDef {
+        name: "trimwhitespace",
+        short: None,
+        scope: Scope::Buffer,
+        kind: Kind::Bool(false),
+    },
+    OptionDef {
+        name: "writebackup",
+        short: Some("wb"),
+        scope: Scope::Global,
+        kind: Kind::Bool(true),
+    },
+];
+
+/// Options that change how a buffer is written to disk, so changing them modifies the buffer.
+pub const FILE_OPTIONS: &[&str] = &["bomb", "endofline", "fileencoding", "fileformat"];
+
+/// Joins the arguments of a `:set` command that were split at an escaped space, e.g. `stl=%f\`
+/// and `%m` to `stl=%f %m`.
+pub fn join_escaped(args: &[&str]) -> Vec<String> {
+    let mut joined: Vec<String> = Vec::new();
+    let mut continued = false;
+
+    for arg in args {
+        match joined.last_mut() {
+            Some(last) if continued => last.push_str(arg),
+            _ => joined.push(String::from(*arg)),
+        }
+
+        let last = joined.last_mut().unwrap();
+        continued = last.ends_with('\\') && !last.ends_with("\\\\");
+
+        if continued {
+            last.pop();
+            last.push(' ');
+        }
+    }
+
+    joined
+}
+
+/// Looks up an option by its full or its short name.
+pub fn find(name: &str) -> Option<&'static OptionDef> {
+    OPTIONS
+        .iter()
+        .find(|def| def.name == name || def.short == Some(name))
+}
+
+/// A single argument of the `:set` command.
+#[derive(Debug, PartialEq)]
+pub enum SetArg<'a> {
+    /// `:set opt`, enables a boolean option or shows the value of any other option
+    Enable(&'a str),
+    /// `:set noopt`
+    Disable(&'a str),
+    /// `:set invopt` or `:set opt!`
+    Toggle(&'a str),
+    /// `:set opt=val`
+    Assign(&'a str, &'a str),
+    /// `:set opt?`
+    Query(&'a str),
+    /// `:set opt&`
+    Reset(&'a str),
+}
+
+impl<'a> SetArg<'a> {
+    pub fn parse(arg: &'a str) -> Self {
+        if let Some(pos) = arg.find(['=', ':']) {
+            Self::Assign(&arg[..pos], &arg[pos + 1..])
+        } else if let Some(name) = arg.strip_suffix('?') {
+            Self::Query(name)
+        } else if let Some(name) = arg.strip_suffix('&') {
+            Self::Reset(name)
+        } else if let Some(name) = arg.strip_suffix('!') {
+            Self::Toggle(name)
+        } else if let Some(name) = arg.strip_prefix("inv") {
+            Self::Toggle(name)
+        } else if arg.starts_with("no") && find(arg).is_none() {
+            Self::Disable(&arg[2..])
+        } else {
+            Self::Enable(arg)
+        }
+    }
+
+    pub fn name(&self) -> &'a str {
+        match *self {
+            Self::Enable(name)
+            | Self::Disable(name)
+            | Self::Toggle(name)
+            | Self::Assign(name, _)
+            | Self::Query(name)
+            | Self::Reset(name) => name,
+        }
+    }
+}
+
+/// Option values of a single scope, only options that were set explicitly are stored.
+#[derive(Clone, Default)]
+pub struct Options {
+    values: HashMap<&'static str, Value>,
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+
+    pub fn set(&mut self, def: &'static OptionDef, value: Value) {
+        self.values.insert(def.name, value);
+    }
+
+    /// Sets an option by its name, panics if there is no such option.
+    pub fn set_by_name(&mut self, name: &str, value: Value) {
+        self.set(find(name).expect("Unknown option"), value);
+    }
+
+    pub fn reset(&mut self, def: &OptionDef) {
+        self.values.remove(def.name);
+    }
+}
+
+/// The global and window-local option values of an editor. Buffer-local values live in their
+/// buffer and are passed in where needed.
+#[derive(Default)]
 pub struct Settings {
-    pub tab_type: TabType,
-    pub tab_width: usize,
+    global: Options,
+    window: Options,
 }
 
 impl Settings {
     pub fn new() -> Settings {
         Settings {
-            tab_type: TabType::Soft,
-            tab_width: 4,
+            global: Options::new(),
+            window: Options::new(),
+        }
+    }
+
+    /// Gets the effective value of an option. Local values take precedence over global ones,
+    /// which in turn take precedence over the default.
+    pub fn get(&self, buffer: &Options, name: &str) -> Option<Value> {
+        let def = find(name)?;
+
+        let local = match def.scope {
+            Scope::Global => None,
+            Scope::Buffer => buffer.get(def.name),
+            Scope::Window => self.window.get(def.name),
+        };
+
+        Some(
+            local
+                .or_else(|| self.global.get(def.name))
+                .cloned()
+                .unwrap_or_else(|| def.default_value()),
+        )
+    }
+
+    /// Sets an option. Without `local_only` this works like `:set`, i.e. both the local and the
+    /// global value are changed, so that new buffers pick up the value as well.
+    pub fn set(
+        &mut self,
+        buffer: &mut Options,
+        def: &'static OptionDef,
+        value: Value,
+        local_only: bool,
+    ) {
+        match def.scope {
+            Scope::Global => {}
+            Scope::Buffer => buffer.set(def, value.clone()),
+            Scope::Window => self.window.set(def, value.clone()),
+        }
+
+        if !local_only || def.scope == Scope::Global {
+            self.global.set(def, value);
+        }
+    }
+
+    /// Resets an option to its default value.
+    pub fn reset(&mut self, buffer: &mut Options, def: &'static OptionDef, local_only: bool) {
+        self.set(buffer, def, def.default_value(), local_only);
+    }
+
+    /// Applies a single `:set` or `:setlocal` argument. Returns a message to be shown to the user
+    /// if the argument asks for one.
+    pub fn apply(
+        &mut self,
+        buffer: &mut Options,
+        arg: &str,
+        local_only: bool,
+    ) -> Result<Option<String>, String> {
+        let arg = SetArg::parse(arg);
+        let def = find(arg.name()).ok_or(format!("Unknown option: {}", arg.name()))?;
+        let current = self.get(buffer, def.name).unwrap();
+
+        match arg {
+            SetArg::Enable(_) if current.as_bool().is_some() => {
+                self.set(buffer, def, Value::Bool(true), local_only)
+            }
+            SetArg::Enable(_) | SetArg::Query(_) => return Ok(Some(format_option(def, &current))),
+            SetArg::Disable(_) | SetArg::Toggle(_) => {
+                let enabled = current
+                    .as_bool()
+                    .ok_or(format!("Invalid argument: {}", def.name))?;
+                let value = matches!(arg, SetArg::Toggle(_)) && !enabled;
+
+                self.set(buffer, def, Value::Bool(value), local_only);
+            }
+            SetArg::Assign(_, text) => {
+                let value = def.parse_value(text)?;
+
+                self.set(buffer, def, value, local_only);
+            }
+            SetArg::Reset(_) => self.reset(buffer, def, local_only),
         }
+
+        Ok(None)
+    }
+
+    /// Lists all options that differ from their default value, as shown by `:set` without arguments.
+    pub fn changed(&self, buffer: &Options) -> Vec<String> {
+        OPTIONS
+            .iter()
+            .filter_map(|def| {
+                let value = self.get(buffer, def.name)?;
+
+                if value != def.default_value() {
+                    Some(format_option(def, &value))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Formats an option the way `:set opt?` shows it.
+pub fn format_option(def: &OptionDef, value: &Value) -> String {
+    match value {
+        Value::Bool(true) => String::from(def.name),
+        Value::Bool(false) => format!("no{}", def.name),
+        _ => format!("{}={}", def.name, value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_arg_parse_test() {
+        assert_eq!(SetArg::Enable("et"), SetArg::parse("et"));
+        assert_eq!(SetArg::Disable("expandtab"), SetArg::parse("noexpandtab"));
+        assert_eq!(SetArg::Toggle("et"), SetArg::parse("invet"));
+        assert_eq!(SetArg::Toggle("et"), SetArg::parse("et!"));
+        assert_eq!(SetArg::Assign("ts", "8"), SetArg::parse("ts=8"));
+        assert_eq!(SetArg::Query("ts"), SetArg::parse("ts?"));
+        assert_eq!(SetArg::Reset("ts"), SetArg::parse("ts&"));
+
+        assert_eq!(
+            vec![String::from("stl=%f %m"), String::from("ts=8")],
+            join_escaped(&["stl=%f\\", "%m", "ts=8"])
+        );
+    }
+
+    #[test]
+    fn apply_test() {
+        let mut settings = Settings::new();
+        let mut buffer = Options::new();
+
+        settings.apply(&mut buffer, "ts=8", false).unwrap();
+        settings.apply(&mut buffer, "noet", false).unwrap();
+
+        assert_eq!(Some(Value::Int(8)), settings.get(&buffer, "tabstop"));
+        assert_eq!(Some(Value::Bool(false)), settings.get(&buffer, "expandtab"));
+        assert_eq!(
+            Some(String::from("tabstop=8")),
+            settings.apply(&mut buffer, "ts?", false).unwrap()
+        );
+
+        settings.apply(&mut buffer, "ts&", false).unwrap();
+        assert_eq!(Some(Value::Int(4)), settings.get(&buffer, "tabstop"));
+
+        assert!(settings.apply(&mut buffer, "ts=abc", false).is_err());
+        assert!(settings.apply(&mut buffer, "ut=0", false).is_err());
+        assert!(settings.apply(&mut buffer, "bex=", false).is_err());
+        assert!(settings.apply(&mut buffer, "foo", false).is_err());
+    }
+
+    #[test]
+    fn setlocal_test() {
+        let mut settings = Settings::new();
+        let mut first = Options::new();
+        let mut second = Options::new();
+
+        settings.apply(&mut first, "ts=2", true).unwrap();
+        assert_eq!(Some(Value::Int(2)), settings.get(&first, "tabstop"));
+        assert_eq!(Some(Value::Int(4)), settings.get(&second, "tabstop"));
+
+        settings.apply(&mut second, "ts=8", false).unwrap();
+        assert_eq!(Some(Value::Int(2)), settings.get(&first, "tabstop"));
+        assert_eq!(
+            Some(Value::Int(8)),
+            settings.get(&Options::new(), "tabstop")
+        );
     }
 }
@@ -0,0 +1,218 @@
+use std::env;
+use std::path::Path;
+
+use crate::editor::{Editor, Mode};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::util::display_width;
+
+/// Expands a `statusline` format into the left and the right part of the status line, which are
+/// separated by `%=`. These items are known:
+///
+/// - `%f` the file path relative to the working directory, `%F` the absolute one, `%t` the name
+/// - `%m` `[+]` if the buffer is modified, `[-]` if it is not modifiable
+/// - `%r` `[RO]` if the buffer is read-only, `%n` `[New]` if the file does not exist yet
+/// - `%y` the filetype in brackets, `%Y` without them
+/// - `%l` the line, `%L` the number of lines, `%p` the percentage through the file, `%P` the
+///   same but `Top`, `Bot` or `All` if the start or end of the file is visible
+/// - `%c` the column in graphemes, `%v` the column on screen
+/// - `%M` the mode if it is not Command mode
+/// - `%{name}` the value of any option, e.g. `%{fileencoding}`
+/// - `%%` a percent sign
+pub fn render(editor: &Editor, format: &str) -> (String, String) {
+    let mut left = String::new();
+    let mut right = String::new();
+    let mut in_right = false;
+    let mut chars = format.chars();
+
+    while let Some(c) = chars.next() {
+        let out = if in_right { &mut right } else { &mut left };
+
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('=') => in_right = true,
+            Some('{') => {
+                let name: String = chars.by_ref().take_while(|&c| c != '}').collect();
+
+                match editor.settings.get(&editor.buffer.options, &name) {
+                    Some(value) => out.push_str(&value.to_string()),
+                    None => out.push_str(&format!("%{{{}}}", name)),
+                }
+            }
+            Some(item) => out.push_str(&expand(editor, item)),
+            None => out.push('%'),
+        }
+    }
+
+    (left, right)
+}
+
+/// Expands a single item of the format.
+fn expand(editor: &Editor, item: char) -> String {
+    let len = editor.buffer.len();
+
+    match item {
+        'f' => display_name(&relative_path(&editor.file_name)),
+        'F' => display_name(&absolute_path(&editor.file_name)),
+        't' => display_name(
+            Path::new(&editor.file_name)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or(""),
+        ),
+        'm' if !editor.option("modifiable").as_bool().unwrap() => String::from("[-]"),
+        'm' if editor.modified => String::from("[+]"),
+        'r' if editor.option("readonly").as_bool().unwrap() => String::from("[RO]"),
+        'n' if editor.buffer.is_new => String::from("[New]"),
+        'm' | 'r' | 'n' => String::new(),
+        'y' | 'Y' => {
+            let filetype = editor.option("filetype");
+            let filetype = filetype.as_str().unwrap();
+
+            if item == 'Y' || filetype.is_empty() {
+                String::from(filetype)
+            } else {
+                format!("[{}]", filetype)
+            }
+        }
+        'l' => editor.current_line.to_string(),
+        'L' => len.to_string(),
+        'p' => format!("{}%", editor.current_line * 100 / len),
+        'P' => {
+            let top = editor.top_line();
+            let bottom = top + (editor.height as usize).saturating_sub(4);
+
+            match (top == 1, bottom >= len) {
+                (true, true) => String::from("All"),
+                (true, false) => String::from("Top"),
+                (false, true) => String::from("Bot"),
+                (false, false) => format!("{}%", editor.current_line * 100 / len),
+            }
+        }
+        'c' => editor.current_char.to_string(),
+        'v' => (editor.x.saturating_sub(editor.start_x()) + 1).to_string(),
+        'M' => match editor.mode_after_command {
+            Some(mode) => format!("-- ({}) -- ", mode.name().to_lowercase()),
+            None if editor.mode != Mode::Command => format!("-- {} -- ", editor.mode.name()),
+            None => String::new(),
+        },
+        '%' => String::from("%"),
+        _ => format!("%{}", item),
+    }
+}
+
+fn display_name(name: &str) -> String {
+    if name.is_empty() {
+        String::from("[No Name]")
+    } else {
+        String::from(name)
+    }
+}
+
+/// Gets the path of a file relative to the working directory, if it is inside of it.
+fn relative_path(file_name: &str) -> String {
+    let path = Path::new(file_name);
+
+    match env::current_dir() {
+        Ok(cwd) if path.is_absolute() => match path.strip_prefix(cwd) {
+            Ok(relative) => relative.to_string_lossy().into_owned(),
+            Err(_) => String::from(file_name),
+        },
+        _ => String::from(file_name),
+    }
+}
+
+fn absolute_path(file_name: &str) -> String {
+    let path = Path::new(file_name);
+
+    match env::current_dir() {
+        Ok(cwd) if !file_name.is_empty() && !path.is_absolute() => {
+            cwd.join(path).to_string_lossy().into_owned()
+        }
+        _ => String::from(file_name),
+    }
+}
+
+/// Puts the left and right part of the status line into the given number of columns. If there
+/// is not enough room, the left part is cut off at its start first, as the end of a path is the
+/// interesting part, then the right one.
+pub fn fit(left: &str, right: &str, width: usize) -> String {
+    let right = truncate_start(right, width);
+    let right_width = display_width(&right);
+    let left = truncate_start(left, width - right_width);
+    let padding = width - right_width - display_width(&left);
+
+    format!("{}{}{}", left, " ".repeat(padding), right)
+}
+
+/// Cuts off the start of a text so that it takes at most `width` columns, marking the cut with a
+/// `<`. A wide character that only fits half is left out, so the text may end up narrower.
+fn truncate_start(text: &str, width: usize) -> String {
+    if display_width(text) <= width {
+        return String::from(text);
+    } else if width == 0 {
+        return String::new();
+    }
+
+    let mut start = text.len();
+    let mut kept = 0;
+
+    for (index, grapheme) in text.grapheme_indices(true).rev() {
+        kept += display_width(grapheme);
+
+        if kept > width - 1 {
+            break;
+        }
+
+        start = index;
+    }
+
+    format!("<{}", &text[start..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_test() {
+        let mut editor = Editor::headless();
+        editor.file_name = String::from("src/main.rs");
+        editor
+            .buffer
+            .replace_line(1, String::from("fn main() {}"))
+            .unwrap();
+        editor.buffer.insert(2, String::new()).unwrap();
+        editor.set_options(&["ft=rust"], true).unwrap();
+        editor.modified = true;
+
+        assert_eq!(
+            (
+                String::from("main.rs [+] [rust]"),
+                String::from("1/2 50% utf-8 %{nope}%"),
+            ),
+            render(&editor, "%t %m%r %y%=%l/%L %p %{fenc} %{nope}%%")
+        );
+        assert_eq!(
+            String::from("[No Name]"),
+            render(&Editor::headless(), "%f").0
+        );
+    }
+
+    #[test]
+    fn fit_test() {
+        assert_eq!("main.rs     1/2", fit("main.rs", "1/2", 15));
+        assert_eq!("<n.rs 1/2", fit("src/main.rs ", "1/2", 9));
+        assert_eq!("<2", fit("main.rs", "1/2", 2));
+        assert_eq!("", fit("main.rs", "1/2", 0));
+
+        // Wide characters take two columns
+        assert_eq!("日本語.rs  1/2", fit("日本語.rs", "1/2", 14));
+        assert_eq!("<語.rs1/2", fit("日本語.rs", "1/2", 9));
+        assert_eq!("<語 ", fit("日本語", "", 4));
+    }
+}
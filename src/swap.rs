@@ -0,0 +1,415 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::buffer::Buffer;
+use crate::editor::Editor;
+use crate::util;
+
+const MAGIC: &str = "rydl swap file";
+
+/// The content of a swap file, i.e. an unsaved buffer together with the state needed to recover it.
+#[derive(Debug, PartialEq)]
+pub struct SwapFile {
+    pub pid: u32,
+    pub host: String,
+    pub file_name: String,
+    pub current_line: usize,
+    pub current_char: usize,
+    pub time: u64,
+    pub lines: Vec<String>,
+}
+
+impl SwapFile {
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "{}\npid: {}\nhost: {}\nfile: {}\nline: {}\nchar: {}\ntime: {}\n\n",
+            MAGIC,
+            self.pid,
+            self.host,
+            self.file_name,
+            self.current_line,
+            self.current_char,
+            self.time
+        );
+
+        text.push_str(&self.lines.join("\n"));
+
+        text
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        let (header, content) = text.split_once("\n\n")?;
+        let mut header = header.lines();
+
+        if header.next()? != MAGIC {
+            return None;
+        }
+
+        let mut swap_file = Self {
+            pid: 0,
+            host: String::new(),
+            file_name: String::new(),
+            current_line: 1,
+            current_char: 1,
+            time: 0,
+            lines: content.split('\n').map(String::from).collect(),
+        };
+
+        for field in header {
+            let (key, value) = field.split_once(": ")?;
+
+            match key {
+                "pid" => swap_file.pid = value.parse().ok()?,
+                "host" => swap_file.host = String::from(value),
+                "file" => swap_file.file_name = String::from(value),
+                "line" => swap_file.current_line = value.parse().ok()?,
+                "char" => swap_file.current_char = value.parse().ok()?,
+                "time" => swap_file.time = value.parse().ok()?,
+                _ => {}
+            }
+        }
+
+        Some(swap_file)
+    }
+
+    /// Whether the process that owns the swap file is still running. Processes on other hosts
+    /// can't be checked, so they are assumed to be running.
+    pub fn owner_running(&self) -> bool {
+        if self.host != hostname() {
+            return true;
+        }
+
+        if self.pid == process::id() {
+            return false;
+        }
+
+        // Signal 0 only checks whether the process exists
+        let result = unsafe { libc::kill(self.pid as libc::pid_t, 0) };
+
+        result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+}
+
+pub fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    let result = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+
+    if result != 0 {
+        return String::new();
+    }
+
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+/// Gets the paths a swap file for the given file can have, ordered by preference. A swap file
+/// that is in use by another process makes the next one to be used.
+pub fn swap_paths(file_name: &str, directory: &str) -> Vec<PathBuf> {
+    let base = util::companion_path(file_name, directory, true);
+
+    ["swp", "swo", "swn"]
+        .iter()
+        .map(|ext| PathBuf::from(format!("{}.{}", base.to_string_lossy(), ext)))
+        .collect()
+}
+
+/// Whether the buffer of an editor is kept in a swap file. Like in Vim, `updatecount=0` turns
+/// swap files off.
+fn uses_swap(editor: &Editor) -> bool {
+    !editor.file_name.is_empty()
+        && editor.option("swapfile").as_bool().unwrap()
+        && editor.option("updatecount").as_int().unwrap() > 0
+}
+
+/// What to do with a file for which a swap file already exists.
+#[derive(Debug, PartialEq)]
+pub enum SwapChoice {
+    /// Recover from the swap file, and replace it if its process is gone
+    Recover(SwapFile, Option<PathBuf>),
+    ReadOnly,
+    Delete,
+    Abort,
+}
+
+pub trait Swap {
+    fn swap_paths(&self) -> Vec<PathBuf>;
+    fn check_swap(&mut self) -> Option<SwapChoice>;
+    fn recover_swap(&mut self, swap_file: SwapFile, replace: Option<PathBuf>);
+    fn update_swap(&mut self);
+    fn flush_swap(&mut self);
+    fn write_swap(&mut self) -> std::io::Result<()>;
+    fn remove_swap(&mut self);
+    fn write_emergency(&mut self) -> std::io::Result<PathBuf>;
+}
+
+impl Swap for Editor {
+    fn swap_paths(&self) -> Vec<PathBuf> {
+        let directory = self.option("directory");
+
+        swap_paths(&self.file_name, directory.as_str().unwrap())
+    }
+
+    /// Checks whether there is a swap file for the current file name, and asks the user what to
+    /// do if there is one. Returns `None` if there is no swap file.
+    fn check_swap(&mut self) -> Option<SwapChoice> {
+        self.swap_file = None;
+
+        for path in self.swap_paths() {
+            let text = fs::read_to_string(&path).ok();
+            let swap_file = match text.and_then(|text| SwapFile::parse(&text)) {
+                Some(swap_file) => swap_file,
+                None => continue,
+            };
+
+            // A swap file of this very process is left over from before reloading the file
+            if swap_file.pid == process::id() && swap_file.host == hostname() {
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+
+            let running = swap_file.owner_running();
+            let question = format!(
+                "Swap file {} found (process {} on {}{}). [r]ecover, [o]pen read-only, [d]elete, [a]bort?",
+                path.display(),
+                swap_file.pid,
+                swap_file.host,
+                if running { ", STILL RUNNING" } else { "" }
+            );
+
+            // Only swap files of processes that are gone can be replaced
+            let choice = match self.prompt(&question, &['r', 'o', 'd', 'a']) {
+                'r' if running => SwapChoice::Recover(swap_file, None),
+                'r' => SwapChoice::Recover(swap_file, Some(path)),
+                'o' => SwapChoice::ReadOnly,
+                'd' => {
+                    let _ = fs::remove_file(&path);
+                    SwapChoice::Delete
+                }
+                _ => SwapChoice::Abort,
+            };
+
+            return Some(choice);
+        }
+
+        None
+    }
+
+    /// Replaces the content of the buffer by the one of a swap file. The swap file at `replace`
+    /// is removed once the recovered buffer is in a new swap file.
+    fn recover_swap(&mut self, swap_file: SwapFile, replace: Option<PathBuf>) {
+        let options = self.buffer.options.clone();
+
+        self.buffer = Buffer::new(false);
+        self.buffer.options = options;
+
+        for line in swap_file.lines {
+            self.buffer.push(line);
+        }
+
+        self.current_line = swap_file.current_line.clamp(1, self.buffer.len());
+        self.current_char = swap_file.current_char.clamp(1, self.current_line_len() + 1);
+        self.set_top_line(self.current_line);
+        self.y = 1;
+        self.sync_x();
+        self.modified = true;
+
+        if let Some(path) = replace {
+            if self.write_swap().is_ok() {
+                let _ = fs::remove_file(path);
+            }
+        }
+
+        self.show_message("Recovered from swap file, write the buffer to keep the changes");
+    }
+
+    /// Writes the swap file if the buffer was changed enough or long enough ago.
+    fn update_swap(&mut self) {
+        if !self.modified || !uses_swap(self) {
+            return;
+        }
+
+        self.changes_since_swap += 1;
+
+        let update_count = self.option("updatecount").as_int().unwrap();
+        let update_time = Duration::from_millis(self.option("updatetime").as_int().unwrap() as u64);
+
+        if self.changes_since_swap >= update_count || self.last_swap.elapsed() >= update_time {
+            if let Err(err) = self.write_swap() {
+                self.show_error(&format!("Could not write swap file: {}", err));
+            }
+        }
+    }
+
+    /// Writes the swap file if there are changes that are not in it yet, used when the user is idle.
+    fn flush_swap(&mut self) {
+        if !self.modified || self.changes_since_swap == 0 || !uses_swap(self) {
+            return;
+        }
+
+        if let Err(err) = self.write_swap() {
+            self.show_error(&format!("Could not write swap file: {}", err));
+        }
+    }
+
+    fn write_swap(&mut self) -> std::io::Result<()> {
+        let mut file = match &self.swap_file {
+            Some(path) => util::create_private(path, false)?,
+            None => {
+                // Use the first swap file name that is not taken by another process
+                let path = self
+                    .swap_paths()
+                    .into_iter()
+                    .find(|path| fs::symlink_metadata(path).is_err())
+                    .ok_or_else(|| std::io::Error::other("Too many swap files"))?;
+
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+
+                let file = util::create_private(&path, true)?;
+                self.swap_file = Some(path);
+                file
+            }
+        };
+
+        let swap_file = SwapFile {
+            pid: process::id(),
+            host: hostname(),
+            file_name: self.file_name.clone(),
+            current_line: self.current_line,
+            current_char: self.current_char,
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs())
+                .unwrap_or(0),
+            lines: self.buffer.iter().cloned().collect(),
+        };
+
+        file.write_all(swap_file.to_text().as_bytes())?;
+        file.sync_all()?;
+
+        self.changes_since_swap = 0;
+        self.last_swap = Instant::now();
+
+        Ok(())
+    }
+
+    fn remove_swap(&mut self) {
+        if let Some(path) = self.swap_file.take() {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Saves the unsaved changes after a crash. They go to the swap file, so that they are offered
+    /// for recovery the next time the file is opened. Buffers without a file name or whose swap
+    /// file can't be written go to a file in the temporary directory instead.
+    fn write_emergency(&mut self) -> std::io::Result<PathBuf> {
+        if !self.file_name.is_empty() && self.write_swap().is_ok() {
+            if let Some(path) = &self.swap_file {
+                return Ok(path.clone());
+            }
+        }
+
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or(0);
+        let path = env::temp_dir().join(format!(
+            "rydl-{}-{}.txt",
+            process::id(),
+            util::format_timestamp(time)
+        ));
+
+        // The name can be guessed, so an existing file (or link) is never written to
+        let lines: Vec<&str> = self.buffer.iter().map(String::as_str).collect();
+        let mut file = util::create_private(&path, true)?;
+        file.write_all((lines.join("\n") + "\n").as_bytes())?;
+
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_file_test() {
+        let swap_file = SwapFile {
+            pid: 42,
+            host: String::from("host"),
+            file_name: String::from("/tmp/file.txt"),
+            current_line: 2,
+            current_char: 3,
+            time: 1234,
+            lines: vec![String::from("first"), String::new(), String::from("third")],
+        };
+
+        let text = swap_file.to_text();
+
+        assert_eq!(Some(swap_file), SwapFile::parse(&text));
+        assert_eq!(None, SwapFile::parse("some other file\n\ncontent"));
+    }
+
+    #[test]
+    fn swap_paths_test() {
+        let paths = swap_paths("dir/file.txt", ".");
+        assert!(paths[0].ends_with("dir/.file.txt.swp"));
+        assert!(paths[1].ends_with("dir/.file.txt.swo"));
+
+        let paths = swap_paths("/home/user/file.txt", "/tmp/swap");
+        assert_eq!(PathBuf::from("/tmp/swap/%home%user%file.txt.swp"), paths[0]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn write_swap_test() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = util::TempDir::new("write-swap");
+
+        let mut editor = Editor::headless();
+        editor.file_name = String::from(dir.join("file.txt").to_str().unwrap());
+        editor.write_swap().unwrap();
+
+        // Swap files hold the content of the buffer, which may be private
+        let path = editor.swap_file.clone().unwrap();
+        assert_eq!(
+            0o600,
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777
+        );
+
+        editor.write_swap().unwrap();
+        editor.remove_swap();
+        assert!(!path.exists());
+
+        // No swap file is written with `updatecount=0`
+        editor.run_command("set updatecount=0");
+        editor.modified = true;
+        editor.update_swap();
+        assert_eq!(None, editor.swap_file);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn write_emergency_test() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut editor = Editor::headless();
+        editor.buffer.set(1, String::from("unsaved")).unwrap();
+        let path = editor.write_emergency().unwrap();
+
+        assert_eq!("unsaved\n", fs::read_to_string(&path).unwrap());
+        assert_eq!(
+            0o600,
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+use std::fs::File;
+use std::io::{stdout, Write};
+use std::os::unix::io::AsRawFd;
+use std::panic;
+use std::sync::Mutex;
+
+use termion::color;
+
+use crate::input;
+
+/// Cursor shapes, the default one is the shape the user configured for their terminal.
+pub const CURSOR_DEFAULT: &str = "\x1b[0 q";
+pub const CURSOR_BLOCK: &str = "\x1b[2 q";
+pub const CURSOR_BAR: &str = "\x1b[6 q";
+pub const CURSOR_UNDERLINE: &str = "\x1b[4 q";
+
+/// The terminal settings from before rydl started, restored when it exits or crashes.
+static ORIGINAL: Mutex<Option<libc::termios>> = Mutex::new(None);
+
+/// Restores the terminal when dropped, even if that happens because of a panic.
+pub struct Guard;
+
+impl Guard {
+    pub fn new() -> Self {
+        save();
+
+        Guard
+    }
+}
+
+impl Default for Guard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+/// Remembers the current terminal settings.
+fn save() {
+    let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+
+    if unsafe { libc::tcgetattr(libc::STDOUT_FILENO, &mut termios) } == 0 {
+        *ORIGINAL.lock().unwrap_or_else(|err| err.into_inner()) = Some(termios);
+    }
+}
+
+/// Puts the terminal back into the state it was in before rydl started, i.e. leaves raw mode and
+/// the alternate screen and shows the cursor again.
+pub fn restore() {
+    let original = *ORIGINAL.lock().unwrap_or_else(|err| err.into_inner());
+
+    if let Some(termios) = original {
+        unsafe { libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, &termios) };
+    }
+
+    let mut stdout = stdout();
+
+    input::set_focus_events(&mut stdout, false);
+
+    let _ = write!(
+        stdout,
+        "{}{}{}{}{}",
+        color::Fg(color::Reset),
+        color::Bg(color::Reset),
+        CURSOR_DEFAULT,
+        termion::cursor::Show,
+        termion::screen::ToMainScreen
+    );
+    let _ = stdout.flush();
+}
+
+/// Switches to the alternate screen, so that the content of the terminal is back when rydl
+/// exits, and clears it.
+pub fn enter() {
+    let mut stdout = stdout();
+
+    let _ = write!(
+        stdout,
+        "{}{}{}{}",
+        termion::screen::ToAlternateScreen,
+        termion::clear::All,
+        termion::cursor::Goto(1, 1),
+        termion::cursor::Show
+    );
+
+    input::set_focus_events(&mut stdout, true);
+}
+
+/// Gives the terminal back to the shell and stops rydl until it is continued, e.g. with `fg`.
+pub fn suspend() {
+    let mut current = unsafe { std::mem::zeroed::<libc::termios>() };
+    let saved = unsafe { libc::tcgetattr(libc::STDOUT_FILENO, &mut current) } == 0;
+
+    restore();
+
+    unsafe { libc::raise(libc::SIGTSTP) };
+
+    // Execution continues here once rydl is in the foreground again
+    if saved {
+        unsafe { libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, &current) };
+    }
+
+    enter();
+}
+
+/// Makes the terminal the standard input again after the original one was read, e.g. when the
+/// buffer was read from a pipe.
+pub fn reopen_stdin() -> std::io::Result<()> {
+    let tty = File::open("/dev/tty")?;
+
+    if unsafe { libc::dup2(tty.as_raw_fd(), libc::STDIN_FILENO) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Makes panics restore the terminal before their message is printed, so that it is readable.
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        restore();
+        default_hook(info);
+    }));
+}
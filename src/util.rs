@@ -1,3 +1,10 @@
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
 pub fn split_string_every(s: &str, n: usize) -> Vec<String> {
     let mut res = Vec::new();
 
@@ -11,6 +18,214 @@ pub fn split_string_every(s: &str, n: usize) -> Vec<String> {
     res
 }
 
+/// Gets the byte index at which the grapheme with the given (0-based) index starts. Indices past
+/// the end of the string map to its length.
+pub fn byte_index(s: &str, index: usize) -> usize {
+    s.grapheme_indices(true)
+        .nth(index)
+        .map(|(i, _)| i)
+        .unwrap_or_else(|| s.len())
+}
+
+pub fn grapheme_count(s: &str) -> usize {
+    s.graphemes(true).count()
+}
+
+/// Gets the number of terminal columns a text without tabs takes up, wide characters like CJK
+/// ones take two.
+pub fn display_width(s: &str) -> usize {
+    s.width()
+}
+
+/// Gets the number of columns a tab at the given (0-based) display column takes up.
+pub fn tab_stop_width(column: usize, tab_width: usize) -> usize {
+    tab_width - column % tab_width
+}
+
+/// Gets the (0-based) display column of the grapheme with the given index, with tabs reaching up
+/// to the next tab stop.
+pub fn display_column(s: &str, index: usize, tab_width: usize) -> usize {
+    s.graphemes(true)
+        .take(index)
+        .fold(0, |column, grapheme| match grapheme {
+            "\t" => column + tab_stop_width(column, tab_width),
+            _ => column + 1,
+        })
+}
+
+/// Replaces tabs with spaces for printing, so that each tab reaches up to the next tab stop.
+pub fn expand_tabs(s: &str, tab_width: usize) -> String {
+    let mut res = String::new();
+    let mut column = 0;
+
+    for grapheme in s.graphemes(true) {
+        match grapheme {
+            "\t" => {
+                let width = tab_stop_width(column, tab_width);
+
+                res.push_str(&" ".repeat(width));
+                column += width;
+            }
+            _ => {
+                res.push_str(grapheme);
+                column += 1;
+            }
+        }
+    }
+
+    res
+}
+
+/// Gets the (0-based) grapheme index of the start of the word before the given index, skipping
+/// whitespace right before it, like `Ctrl-w` deletes it. Punctuation counts as a word of its own.
+pub fn word_start(s: &str, index: usize) -> usize {
+    let graphemes: Vec<&str> = s.graphemes(true).take(index).collect();
+    let is_word = |g: &str| g.chars().all(|c| c.is_alphanumeric() || c == '_');
+    let mut start = graphemes.len();
+
+    while start > 0 && graphemes[start - 1].chars().all(char::is_whitespace) {
+        start -= 1;
+    }
+
+    match start.checked_sub(1).map(|i| is_word(graphemes[i])) {
+        Some(true) => {
+            while start > 0 && is_word(graphemes[start - 1]) {
+                start -= 1;
+            }
+        }
+        Some(false) => start -= 1,
+        None => {}
+    }
+
+    start
+}
+
+/// Gets the leading whitespace of a line.
+pub fn indentation(s: &str) -> &str {
+    &s[..s.len() - s.trim_start_matches([' ', '\t']).len()]
+}
+
+/// Gets the number of columns the leading whitespace of a line takes up.
+pub fn indent_width(s: &str, tab_width: usize) -> usize {
+    let indentation = indentation(s);
+
+    display_column(indentation, indentation.len(), tab_width)
+}
+
+/// Expands a leading `~` in a path to the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix('~') {
+        Some(rest) => match env::var("HOME") {
+            Ok(home) => Path::new(&home).join(rest.trim_start_matches('/')),
+            Err(_) => PathBuf::from(path),
+        },
+        None => PathBuf::from(path),
+    }
+}
+
+/// Opens a file for writing that only the user can read, used for copies of buffers like swap
+/// files. With `create_new` the file must not exist yet, otherwise it must be one created like
+/// this before, which is truncated. Symlinks are never followed, so that a link planted in a
+/// shared directory can't redirect the write.
+pub fn create_private(path: &Path, create_new: bool) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+
+    if create_new {
+        options.create_new(true);
+    } else {
+        options.truncate(true);
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600).custom_flags(libc::O_NOFOLLOW);
+    }
+
+    options.open(path)
+}
+
+/// Gets the path of a file accompanying another one, like a swap or backup file. With
+/// `directory` set to `.` it is put next to the file (hidden, if requested), otherwise into the
+/// given directory with the full path of the file encoded into its name, so that files with the
+/// same name in different directories don't clash.
+pub fn companion_path(file_name: &str, directory: &str, hidden: bool) -> PathBuf {
+    let path = match env::current_dir() {
+        Ok(dir) => dir.join(file_name),
+        Err(_) => PathBuf::from(file_name),
+    };
+
+    if directory == "." {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+        path.with_file_name(format!("{}{}", if hidden { "." } else { "" }, name))
+    } else {
+        expand_home(directory).join(path.to_string_lossy().replace('/', "%"))
+    }
+}
+
+/// Formats a Unix timestamp as `YYYYMMDD-HHMMSS` in UTC.
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+
+    // Convert the number of days since the epoch to a date in the Gregorian calendar
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// A directory for the files of a test. It is removed when the guard is dropped, also if the test
+/// fails before cleaning up.
+#[cfg(test)]
+pub struct TempDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("rydl-{}-{}", name, std::process::id()));
+
+        // Start empty even if an earlier run was killed before removing it
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+
+        Self(path)
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.0
+    }
+
+    pub fn join(&self, name: &str) -> std::path::PathBuf {
+        self.0.join(name)
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -37,4 +252,67 @@ mod tests {
 
         assert_eq!(res_expected, res);
     }
+
+    #[test]
+    fn display_column_test() {
+        assert_eq!(0, display_column("\tabc", 0, 4));
+        assert_eq!(4, display_column("\tabc", 1, 4));
+        assert_eq!(2, display_column("ab\tc", 2, 4));
+        assert_eq!(4, display_column("ab\tc", 3, 4));
+        assert_eq!(8, display_column("abcd\te", 5, 4));
+    }
+
+    #[test]
+    fn expand_tabs_test() {
+        assert_eq!("    abc", expand_tabs("\tabc", 4));
+        assert_eq!("ab  c", expand_tabs("ab\tc", 4));
+        assert_eq!("ab      c", expand_tabs("ab\t\tc", 4));
+    }
+
+    #[test]
+    fn indent_width_test() {
+        assert_eq!(0, indent_width("abc", 4));
+        assert_eq!(6, indent_width("\t  abc", 4));
+        assert_eq!(8, indent_width("  \t\tabc", 4));
+    }
+
+    #[test]
+    fn format_timestamp_test() {
+        assert_eq!("19700101-000000", format_timestamp(0));
+        assert_eq!("20000229-123456", format_timestamp(951_827_696));
+        assert_eq!("20261019-080000", format_timestamp(1_792_396_800));
+    }
+
+    #[test]
+    fn companion_path_test() {
+        assert!(companion_path("dir/file.txt", ".", true).ends_with("dir/.file.txt"));
+        assert!(companion_path("dir/file.txt", ".", false).ends_with("dir/file.txt"));
+        assert_eq!(
+            PathBuf::from("/tmp/backup/%home%user%file.txt"),
+            companion_path("/home/user/file.txt", "/tmp/backup", false)
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn create_private_test() {
+        use std::os::unix::fs::symlink;
+
+        let dir = TempDir::new("create-private");
+        let path = dir.join("file");
+
+        assert!(create_private(&path, false).is_err());
+        create_private(&path, true).unwrap();
+        assert!(create_private(&path, true).is_err());
+        create_private(&path, false).unwrap();
+
+        // Links are not followed, neither when creating nor when reopening
+        let target = dir.join("target");
+        let link = dir.join("link");
+        std::fs::write(&target, "").unwrap();
+        symlink(&target, &link).unwrap();
+
+        assert!(create_private(&link, true).is_err());
+        assert!(create_private(&link, false).is_err());
+    }
 }